@@ -0,0 +1,24 @@
+use crate::state::write_atomically;
+use anyhow::{Context, Result};
+use chrono::Duration;
+use std::fs;
+use std::path::Path;
+
+/// Read the cumulative execution time stored in milliseconds in a `--budget-file`, a missing file
+/// means nothing was spent yet.
+pub fn read_used(path: &Path) -> Result<Duration> {
+    if !path.exists() {
+        return Ok(Duration::zero());
+    }
+    let content =
+        fs::read_to_string(path).with_context(|| format!("cannot read budget file {:?}", path))?;
+    let millis: i64 = content
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid budget file {:?}", path))?;
+    Ok(Duration::milliseconds(millis))
+}
+
+pub fn write_used(path: &Path, used: Duration) -> Result<()> {
+    write_atomically(path, used.num_milliseconds().to_string())
+}
@@ -0,0 +1,79 @@
+use anyhow::{Context, Result};
+use chrono::{Datelike, NaiveDate, Weekday};
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+
+/// Read a `--holidays` file, one ISO date (e.g. 2025-12-25) per line. Blank lines and lines
+/// starting with `#` are ignored.
+pub fn read_holidays(path: &Path) -> Result<HashSet<NaiveDate>> {
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("cannot read holidays file {:?}", path))?;
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            NaiveDate::parse_from_str(line, "%Y-%m-%d")
+                .with_context(|| format!("invalid date {:?} in holidays file {:?}", line, path))
+        })
+        .collect()
+}
+
+/// Why nothing should run on `date`, if it is a weekend with `business_days_only` or a holiday.
+pub fn day_off(
+    date: NaiveDate,
+    business_days_only: bool,
+    holidays: &HashSet<NaiveDate>,
+) -> Option<&'static str> {
+    if business_days_only && matches!(date.weekday(), Weekday::Sat | Weekday::Sun) {
+        Some("weekend")
+    } else if holidays.contains(&date) {
+        Some("holiday")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::calendar::{day_off, read_holidays};
+    use chrono::NaiveDate;
+    use std::collections::HashSet;
+    use std::io::Write;
+
+    fn date(value: &str) -> NaiveDate {
+        value.parse().unwrap()
+    }
+
+    #[test]
+    fn day_off_weekend() {
+        let no_holidays = HashSet::new();
+        assert_eq!(
+            day_off(date("2025-01-04"), true, &no_holidays),
+            Some("weekend")
+        );
+        assert_eq!(
+            day_off(date("2025-01-05"), true, &no_holidays),
+            Some("weekend")
+        );
+        assert_eq!(day_off(date("2025-01-06"), true, &no_holidays), None);
+        assert_eq!(day_off(date("2025-01-04"), false, &no_holidays), None);
+    }
+
+    #[test]
+    fn day_off_holidays_file() {
+        let mut file = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "# Bank holidays\n2025-12-25\n\n2026-01-01\n").unwrap();
+        let holidays = read_holidays(file.path()).unwrap();
+        assert_eq!(holidays.len(), 2);
+        assert_eq!(
+            day_off(date("2025-12-25"), false, &holidays),
+            Some("holiday")
+        );
+        assert_eq!(day_off(date("2025-12-26"), true, &holidays), None);
+
+        writeln!(file, "01/02/2026").unwrap();
+        read_holidays(file.path()).expect_err("invalid date");
+    }
+}
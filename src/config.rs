@@ -0,0 +1,35 @@
+use crate::cronthat::CronThat;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Jobs loaded with `--config`, each one accepts the same options as the command line.
+///
+/// ```toml
+/// [[jobs]]
+/// name = "backup"
+/// cron_expression = "0 0 2 * * *"
+/// command = ["./scripts/backup.sh"]
+/// stop_on_error = true
+/// ```
+#[derive(Serialize, Deserialize)]
+pub struct Config {
+    pub jobs: Vec<Job>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Job {
+    /// Name used in the summary, defaults to the job's position in the file.
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub cronthat: CronThat,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config> {
+        let content =
+            fs::read_to_string(path).with_context(|| format!("cannot read config {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("invalid config {:?}", path))
+    }
+}
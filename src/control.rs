@@ -0,0 +1,361 @@
+use crate::log::{debug, warning};
+use crate::shutdown::Shutdown;
+use anyhow::{bail, Context, Result};
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How often a sleep checks for a shutdown, which doesn't notify the control's condvar.
+static SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Number of SIGUSR1 received since the handler was installed, each control watching the signal
+/// compares it with the number it has seen so that concurrent jobs all get it.
+static RUN_NOW_SIGNALS: AtomicUsize = AtomicUsize::new(0);
+/// Number of SIGHUP received since the handler was installed, like `RUN_NOW_SIGNALS`.
+static RELOAD_SIGNALS: AtomicUsize = AtomicUsize::new(0);
+
+/// Command read from the control FIFO, one per line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ControlCommand {
+    /// Skip the scheduled executions until resumed
+    Pause,
+    Resume,
+    /// Run the command immediately
+    RunNow,
+    /// Stop waiting and read the schedule again
+    Reload,
+    Quit,
+}
+
+impl FromStr for ControlCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<ControlCommand> {
+        Ok(match value {
+            "pause" => ControlCommand::Pause,
+            "resume" => ControlCommand::Resume,
+            "run-now" => ControlCommand::RunNow,
+            "reload" => ControlCommand::Reload,
+            "quit" => ControlCommand::Quit,
+            _ => bail!("unknown control command {:?}", value),
+        })
+    }
+}
+
+/// Why `Control::sleep` returned.
+#[derive(Debug, PartialEq)]
+pub enum Wake {
+    Elapsed,
+    Shutdown,
+    RunNow,
+    Reload,
+}
+
+#[derive(Default)]
+struct Requests {
+    paused: bool,
+    run_now: bool,
+    reload: bool,
+    /// `RUN_NOW_SIGNALS` already handled, if SIGUSR1 is watched.
+    signals_seen: Option<usize>,
+    /// `RELOAD_SIGNALS` already handled, if SIGHUP is watched.
+    hangups_seen: Option<usize>,
+}
+
+/// Runtime control of the scheduling loop, fed by the commands written to `--control-fifo`.
+#[derive(Clone, Default)]
+pub struct Control(Arc<(Mutex<Requests>, Condvar)>);
+
+impl Control {
+    /// Create the FIFO at `path` if needed and apply the commands written to it from a background
+    /// thread, `quit` triggers `shutdown`.
+    pub fn listen(path: &Path, shutdown: &Shutdown) -> Result<Control> {
+        match fs::metadata(path) {
+            Ok(metadata) if !is_fifo(&metadata) => {
+                bail!("control FIFO {:?} exists and is not a named pipe", path)
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => mkfifo(path)?,
+            Err(err) => {
+                return Err(err).with_context(|| format!("cannot stat control FIFO {:?}", path))
+            }
+        }
+        let control = Control::default();
+        let listener = control.clone();
+        let shutdown = shutdown.clone();
+        let path = path.to_path_buf();
+        thread::spawn(move || listener.read_commands(&path, &shutdown));
+        Ok(control)
+    }
+
+    /// Read the FIFO forever, opening it again each time the last writer closes it.
+    fn read_commands(&self, path: &PathBuf, shutdown: &Shutdown) {
+        loop {
+            let file = match File::open(path) {
+                Ok(file) => file,
+                Err(err) => {
+                    warning!("warning: cannot read control FIFO {:?}: {}", path, err);
+                    return;
+                }
+            };
+            for line in BufReader::new(file).lines().map_while(Result::ok) {
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                match line.parse() {
+                    Ok(command) => self.apply(command, shutdown),
+                    Err(err) => warning!("warning: {}, ignoring it", err),
+                }
+            }
+        }
+    }
+
+    /// Run the command immediately when the process receives SIGUSR1.
+    pub fn run_now_on_signal(&self) -> Result<()> {
+        install_signal_handler(SIGUSR1)?;
+        self.0 .0.lock().unwrap().signals_seen = Some(RUN_NOW_SIGNALS.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    /// Stop waiting and read the schedule again when the process receives SIGHUP.
+    pub fn reload_on_signal(&self) -> Result<()> {
+        install_signal_handler(SIGHUP)?;
+        self.0 .0.lock().unwrap().hangups_seen = Some(RELOAD_SIGNALS.load(Ordering::SeqCst));
+        Ok(())
+    }
+
+    pub fn apply(&self, command: ControlCommand, shutdown: &Shutdown) {
+        debug!("Control command {:?} received", command);
+        let (requests, condvar) = &*self.0;
+        let mut requests = requests.lock().unwrap();
+        match command {
+            ControlCommand::Pause => requests.paused = true,
+            ControlCommand::Resume => requests.paused = false,
+            ControlCommand::RunNow => requests.run_now = true,
+            ControlCommand::Reload => requests.reload = true,
+            ControlCommand::Quit => shutdown.trigger(),
+        }
+        condvar.notify_all();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0 .0.lock().unwrap().paused
+    }
+
+    /// Sleep for `duration` unless a shutdown, `run-now` or `reload` comes first.
+    pub fn sleep(&self, duration: Duration, shutdown: &Shutdown) -> Wake {
+        let deadline = Instant::now() + duration;
+        let (requests, condvar) = &*self.0;
+        let mut requests = requests.lock().unwrap();
+        loop {
+            if shutdown.is_triggered() {
+                return Wake::Shutdown;
+            }
+            if requests.run_now {
+                requests.run_now = false;
+                return Wake::RunNow;
+            }
+            if let Some(seen) = requests.signals_seen {
+                let received = RUN_NOW_SIGNALS.load(Ordering::SeqCst);
+                if received != seen {
+                    debug!("SIGUSR1 received");
+                    requests.signals_seen = Some(received);
+                    return Wake::RunNow;
+                }
+            }
+            if let Some(seen) = requests.hangups_seen {
+                let received = RELOAD_SIGNALS.load(Ordering::SeqCst);
+                if received != seen {
+                    debug!("SIGHUP received");
+                    requests.hangups_seen = Some(received);
+                    return Wake::Reload;
+                }
+            }
+            if requests.reload {
+                requests.reload = false;
+                return Wake::Reload;
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Wake::Elapsed;
+            }
+            let timeout = (deadline - now).min(SHUTDOWN_POLL_INTERVAL);
+            requests = condvar.wait_timeout(requests, timeout).unwrap().0;
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_fifo(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    metadata.file_type().is_fifo()
+}
+
+#[cfg(not(unix))]
+fn is_fifo(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+#[cfg(unix)]
+fn mkfifo(path: &Path) -> Result<()> {
+    use std::os::unix::ffi::OsStrExt;
+
+    let c_path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    // SAFETY: `c_path` is a valid NUL-terminated string.
+    if unsafe { libc::mkfifo(c_path.as_ptr(), 0o600) } == -1 {
+        return Err(std::io::Error::last_os_error())
+            .with_context(|| format!("cannot create control FIFO {:?}", path));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn mkfifo(_path: &Path) -> Result<()> {
+    bail!("--control-fifo is only supported on Unix")
+}
+
+#[cfg(unix)]
+use libc::{SIGHUP, SIGUSR1};
+#[cfg(not(unix))]
+const SIGUSR1: i32 = 10;
+#[cfg(not(unix))]
+const SIGHUP: i32 = 1;
+
+/// Count the `signal`s received, SIGUSR1 in `RUN_NOW_SIGNALS` and SIGHUP in `RELOAD_SIGNALS`.
+#[cfg(unix)]
+fn install_signal_handler(signal: libc::c_int) -> Result<()> {
+    extern "C" fn on_signal(signal: libc::c_int) {
+        match signal {
+            SIGUSR1 => RUN_NOW_SIGNALS.fetch_add(1, Ordering::SeqCst),
+            _ => RELOAD_SIGNALS.fetch_add(1, Ordering::SeqCst),
+        };
+    }
+
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only touches atomics, which is async-signal-safe.
+    if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn install_signal_handler(signal: i32) -> Result<()> {
+    match signal {
+        SIGUSR1 => bail!("--run-now-on-signal is only supported on Unix"),
+        _ => bail!("reloading the schedule on SIGHUP is only supported on Unix"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::control::{Control, ControlCommand, Wake};
+    use crate::shutdown::Shutdown;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[cfg(unix)]
+    #[test]
+    fn control_rejects_regular_file() {
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), "quit\n").unwrap();
+        let shutdown = Shutdown::default();
+        let err = Control::listen(file.path(), &shutdown).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "control FIFO {:?} exists and is not a named pipe",
+                file.path()
+            )
+        );
+        assert!(!shutdown.is_triggered());
+    }
+
+    #[test]
+    fn control_wakes_sleep() {
+        let shutdown = Shutdown::default();
+        let control = Control::default();
+        assert_eq!(
+            control.sleep(Duration::from_millis(10), &shutdown),
+            Wake::Elapsed
+        );
+
+        for (command, expected) in [
+            (ControlCommand::RunNow, Wake::RunNow),
+            (ControlCommand::Reload, Wake::Reload),
+            (ControlCommand::Quit, Wake::Shutdown),
+        ] {
+            let start = Instant::now();
+            let (sender, sender_shutdown) = (control.clone(), shutdown.clone());
+            thread::spawn(move || {
+                thread::sleep(Duration::from_millis(50));
+                sender.apply(command, &sender_shutdown);
+            });
+            assert_eq!(control.sleep(Duration::from_secs(10), &shutdown), expected);
+            assert!(start.elapsed() < Duration::from_secs(1));
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn control_run_now_on_signal() {
+        let shutdown = Shutdown::default();
+        let (control, other) = (Control::default(), Control::default());
+        control.run_now_on_signal().unwrap();
+        other.run_now_on_signal().unwrap();
+
+        // SAFETY: the SIGUSR1 handler is installed above.
+        unsafe { libc::raise(libc::SIGUSR1) };
+        assert_eq!(
+            control.sleep(Duration::from_secs(10), &shutdown),
+            Wake::RunNow
+        );
+        assert_eq!(
+            other.sleep(Duration::from_secs(10), &shutdown),
+            Wake::RunNow
+        );
+        assert_eq!(
+            control.sleep(Duration::from_millis(10), &shutdown),
+            Wake::Elapsed
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn control_reload_on_signal() {
+        let shutdown = Shutdown::default();
+        let control = Control::default();
+        control.reload_on_signal().unwrap();
+
+        // SAFETY: the SIGHUP handler is installed above.
+        unsafe { libc::raise(libc::SIGHUP) };
+        assert_eq!(
+            control.sleep(Duration::from_secs(10), &shutdown),
+            Wake::Reload
+        );
+        assert_eq!(
+            control.sleep(Duration::from_millis(10), &shutdown),
+            Wake::Elapsed
+        );
+    }
+
+    #[test]
+    fn control_pause_resume() {
+        let shutdown = Shutdown::default();
+        let control = Control::default();
+        control.apply("pause".parse().unwrap(), &shutdown);
+        assert!(control.is_paused());
+        control.apply("resume".parse().unwrap(), &shutdown);
+        assert!(!control.is_paused());
+        "restart"
+            .parse::<ControlCommand>()
+            .expect_err("unknown command");
+    }
+}
@@ -1,162 +1,5765 @@
-use anyhow::{bail, Context, Result};
-use chrono::{DateTime, Duration, Local, NaiveDateTime};
-use clap::Parser;
+use crate::budget;
+use crate::calendar;
+use crate::config::{Config, Job};
+use crate::control::{Control, Wake};
+use crate::daemon;
+use crate::daemon::Fork;
+use crate::duration;
+use crate::duration::{format_duration, parse_duration};
+use crate::lock;
+use crate::lock::{LockFile, PidLock};
+use crate::log;
+use crate::log::{debug, error, info, trace, warning, LogLevel};
+use crate::marker::Marker;
+use crate::output;
+use crate::output::{
+    FlushMode, JsonField, LineLimit, LinePrefix, OutputEncoding, RunRecord, Transcode,
+};
+use crate::pushgateway;
+use crate::pushgateway::Metrics;
+use crate::schedule;
+use crate::secret;
+use crate::secret::EnvFile;
+use crate::shutdown::Shutdown;
+use crate::state::{write_atomically, Failure, State};
+use crate::status;
+use crate::status::LogBuffer;
+use crate::tag::Tag;
+use crate::tee;
+use crate::timestamp::TimestampFormat;
+use crate::tui;
+use crate::tui::Dashboard;
+use crate::usage::{self, MemoryLimit, Usage};
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, Duration, Local, NaiveDateTime, SubsecRound};
+use chrono_tz::Tz;
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
 use cron::Schedule;
-use run_script::types::IoOptions;
-use run_script::ScriptOptions;
-use std::str::FromStr;
-use std::thread::sleep;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::io::{IsTerminal, Read, Write};
+use std::iter;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::process::{ChildStdout, Command, Stdio};
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::sync::OnceLock;
+use std::thread;
+use uuid::Uuid;
 
-static DATETIME_FORMAT: &'static str = "%Y-%m-%d %H:%M:%S";
+static DATETIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
 
 /// Schedule commands for execution in an interactive shell with cron expressions. It will keep
 /// executing the provided command until interrupted or until specified conditions are met.
-#[derive(Parser)]
+#[derive(Parser, Clone, Default, Serialize, Deserialize)]
 #[command(version, about, author)]
+#[serde(default)]
 pub struct CronThat {
     /// Cron expression to schedule your command, you can use tools like https://crontab.cronhub.io/ to help you.
-    /// Precision up to the second.
-    cron_expression: String,
+    /// Precision up to the second. "@reboot" runs the command once at startup.
+    #[arg(required_unless_present_any = [
+        "config", "schedule_file", "at_time", "days", "months", "weekdays"
+    ])]
+    cron_expression: Option<String>,
 
     /// Command to run
     #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     command: Vec<String>,
 
+    /// Run the --command commands in turn, one per execution, going back to the first one after
+    /// the last one
+    #[arg(long, requires = "commands", conflicts_with_all = ["command", "pipeline"])]
+    cycle: bool,
+
+    /// Command run by --cycle (can be repeated)
+    #[arg(long = "command", requires = "cycle")]
+    commands: Vec<String>,
+
     /// Stop when the command returns a non-zero exit code.
     #[arg(short = 'e', long)]
     stop_on_error: bool,
 
-    /// Number of times the command should be executed (mutually exclusive with --until)
-    #[clap(short('n'), long)]
-    repetitions: Option<usize>,
+    /// Exit codes of successful executions (e.g. 0,2,75), any other code is a failure. Defaults
+    /// to 0
+    #[clap(long, value_delimiter = ',', allow_hyphen_values = true)]
+    success_codes: Vec<i32>,
+
+    /// Stop successfully once the command exits with this code, which is not retried, e.g. a
+    /// polling job signalling it is done
+    #[clap(long, value_name = "CODE")]
+    run_until_code: Option<i32>,
+
+    /// Number of times the command should be executed
+    #[clap(short('n'), long)]
+    repetitions: Option<usize>,
+
+    /// When to stop
+    #[clap(short, long, value_parser = parse_date_time)]
+    until: Option<DateTime<Local>>,
+
+    /// Whether to stop once --repetitions or --until is reached, or only once both are
+    #[clap(long, value_enum, default_value_t = StopWhen::Any)]
+    stop_when: StopWhen,
+
+    /// Fail if no execution succeeded by the time --until is reached, e.g. when cronthat is used
+    /// as a retry loop in CI
+    #[clap(long, requires = "until")]
+    fail_if_no_executions: bool,
+
+    /// Fail instead of stopping successfully when the schedule has no upcoming execution left,
+    /// e.g. a year field in the past
+    #[clap(long)]
+    loop_forever: bool,
+
+    /// Schedule a first execution immediately, the following ones stay aligned on the schedule
+    /// rather than on the time cronthat started
+    #[clap(short('w'), long)]
+    now: bool,
+
+    /// Don't schedule a first execution immediately, even if a --config job sets `now`
+    #[clap(long, overrides_with = "now")]
+    #[serde(skip)]
+    no_now: bool,
+
+    /// Skip the executions scheduled on Saturdays and Sundays, they don't count toward
+    /// --repetitions
+    #[clap(long)]
+    business_days_only: bool,
+
+    /// Skip the executions scheduled on the dates listed in this file, one ISO date (e.g.
+    /// 2025-12-25) per line
+    #[clap(long)]
+    holidays: Option<PathBuf>,
+
+    /// Let the first scheduled executions go by without running the command, they don't count
+    /// toward --repetitions
+    #[clap(long, default_value_t = 0)]
+    skip_first: usize,
+
+    /// Number of the first execution in CRONTHAT_ITERATION and `{iteration}`, e.g. to resume a
+    /// batch. --repetitions still counts the executions of this process
+    #[clap(long, default_value_t = 0)]
+    count_from: usize,
+
+    /// Only run the command on every Nth scheduled execution, the others don't count toward
+    /// --repetitions
+    #[clap(long, visible_alias = "every-x-occurrences")]
+    #[serde(alias = "every_x_occurrences")]
+    every_nth: Option<usize>,
+
+    /// Show the command and its schedule and ask for confirmation before starting, fails when
+    /// stdin is not a terminal unless --yes is given
+    #[clap(long)]
+    confirm: bool,
+
+    /// Answer yes to --confirm without prompting
+    #[clap(long, short('y'))]
+    yes: bool,
+
+    /// Coalesce the executions scheduled less than this after the last one into the next run, which
+    /// gets their number in CRONTHAT_COALESCED_TICKS
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    batch_window: Option<Duration>,
+
+    /// Skip the executions scheduled less than this after the previous one finished, e.g. when
+    /// ticks fire in a burst after a clock change. They don't count toward --repetitions
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    dedup_window: Option<Duration>,
+
+    /// Fail if an execution is still running when the next one is scheduled, instead of running
+    /// the late one right after it
+    #[clap(long)]
+    assert_no_overlap: bool,
+
+    /// Fail when this many executions in a row are skipped by --every-nth, --dedup-window,
+    /// --business-days-only, --holidays, a pause or another instance, --skip-first excluded
+    #[clap(long, visible_alias = "max-skips")]
+    #[serde(alias = "max_skips")]
+    max_consecutive_skips: Option<usize>,
+
+    /// Print dates in RFC 3339 format, e.g. 2025-01-02T15:04:00.123+01:00
+    #[clap(long)]
+    iso_dates: bool,
+
+    /// Format of the timestamps printed by cronthat: iso, epoch (milliseconds), rfc3339 or a
+    /// strftime format such as "%H:%M:%S" (overrides --iso-dates)
+    #[clap(long)]
+    timestamp_format: Option<TimestampFormat>,
+
+    /// Most verbose level of cronthat's own messages to print, the command's output is always
+    /// printed
+    #[clap(long, value_enum, env = "CRONTHAT_LOG", default_value_t = LogLevel::Info)]
+    #[serde(skip)]
+    log_level: LogLevel,
+
+    /// Only print errors, the same as --log-level error
+    #[clap(long)]
+    #[serde(skip)]
+    quiet: bool,
+
+    /// Resolve the symlinks of every path argument at startup, files that don't exist yet through
+    /// their directory. Paths are used as given by default
+    #[clap(long)]
+    #[serde(skip)]
+    follow_symlinks: bool,
+
+    /// Run in the background, detached from the terminal, in the current working directory (Unix
+    /// only)
+    #[clap(long)]
+    #[serde(skip)]
+    daemon: bool,
+
+    /// File the output of --daemon is appended to, discarded by default
+    #[clap(long, requires = "daemon")]
+    #[serde(skip)]
+    log_file: Option<PathBuf>,
+
+    /// File the PID of --daemon is written to
+    #[clap(long, requires = "daemon")]
+    #[serde(skip)]
+    pid_file: Option<PathBuf>,
+
+    /// Show a dashboard of the executions instead of the output, which is discarded (Unix only,
+    /// ignored when stdout is not a terminal)
+    #[clap(long, conflicts_with_all = ["daemon", "tee", "config"])]
+    #[serde(skip)]
+    tui: bool,
+
+    /// Also append cronthat's output and errors, the command's included, to this file (can be
+    /// repeated, Unix only)
+    #[clap(long)]
+    #[serde(skip)]
+    tee: Vec<PathBuf>,
+
+    /// Named pipe (created if missing) to control cronthat while it runs by writing one of pause,
+    /// resume, run-now, reload and quit per line (Unix only)
+    #[clap(long)]
+    control_fifo: Option<PathBuf>,
+
+    /// Run the command immediately, off schedule, when cronthat receives SIGUSR1 (Unix only)
+    #[clap(long)]
+    run_now_on_signal: bool,
+
+    /// Don't count the executions triggered by run-now or --run-now-on-signal toward
+    /// --repetitions
+    #[clap(long)]
+    exclude_manual_runs: bool,
+
+    /// Kill each execution still running after this long (e.g. 30s), it then exits with 124
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    timeout_per_run: Option<Duration>,
+
+    /// Stop after this long (e.g. 1h), killing the execution running at that time
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    timeout_total: Option<Duration>,
+
+    /// Prefix the command with this wrapper, e.g. "timeout 30" to let timeout(1) enforce a time
+    /// limit, each --pipeline stage is wrapped separately
+    #[clap(long)]
+    wrap_in_timeout_cmd: Option<String>,
+
+    /// Exit code the command uses to report a timeout (e.g. 124 for timeout(1)), executions
+    /// exiting with it are handled like the ones killed by --timeout-per-run and exit with 124
+    #[clap(long, value_name = "N", allow_hyphen_values = true)]
+    command_timeout_exit_code: Option<i32>,
+
+    /// Explain how long cronthat sleeps and until when before each execution
+    #[clap(long)]
+    explain: bool,
+
+    /// Text written to the command's stdin on each execution, "{scheduled}" (RFC 3339),
+    /// "{timestamp}" (Unix seconds), "{date}" and "{time}" are replaced with the scheduled time
+    #[clap(long)]
+    stdin_template: Option<String>,
+
+    /// Generate a UUID for each execution, retries included, logged and exposed to the command as
+    /// CRONTHAT_TRACE_ID
+    #[clap(long)]
+    trace_id: bool,
+
+    /// Reuse the trace ID found in this environment variable instead of generating one
+    #[clap(long)]
+    trace_id_from: Option<String>,
+
+    /// Run the command on this host through ssh, e.g. "deploy@web1", scheduling, retries and
+    /// timeouts stay local. The CRONTHAT_* variables are exported there, --env-from-file ones are
+    /// not to keep secrets off the command line
+    #[clap(long, value_name = "DESTINATION")]
+    ssh: Option<String>,
+
+    /// Extra options given to ssh with --ssh, e.g. "-p 2222 -o ConnectTimeout=5"
+    #[clap(long, requires = "ssh", allow_hyphen_values = true)]
+    ssh_opts: Option<String>,
+
+    /// Command run after every execution whatever its exit code, with CRONTHAT_EXIT_CODE,
+    /// CRONTHAT_DURATION_MS and CRONTHAT_RUN_NUMBER set. Its exit code is only logged
+    #[clap(long)]
+    after_command: Option<String>,
+
+    /// Run the command as a pipeline, stages are separated by ":::" and each stage's stdout is
+    /// chained to the next stage's stdin. Fails if any stage fails (pipefail).
+    #[clap(long)]
+    pipeline: bool,
+
+    /// Feed the command's stdout to this command's stdin on each execution, the execution fails
+    /// if either of them fails
+    #[clap(long)]
+    pipe_to: Option<String>,
+
+    /// Persist the scheduler state (runs, last run time and failures) to this JSON file after each
+    /// execution and resume from it at startup
+    #[clap(long)]
+    state_file: Option<PathBuf>,
+
+    /// Write the time of the last successful execution to this file, e.g. for monitoring tools
+    /// checking its modification time
+    #[clap(long)]
+    last_run_file: Option<PathBuf>,
+
+    /// Write the details of each failed execution to this JSON file, removed again by the next
+    /// successful one
+    #[clap(long)]
+    failure_file: Option<PathBuf>,
+
+    /// Run immediately at startup if the last success recorded in --state-file is older than this
+    /// (e.g. 5m) or if there is none
+    #[clap(long, value_parser = parse_duration, requires = "state_file")]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    run_at_startup_if_missed: Option<Duration>,
+
+    /// Run immediately at startup if an execution was scheduled since the last run recorded in
+    /// --state-file, like anacron, or if there is none
+    #[clap(long, requires = "state_file")]
+    run_on_start_if_overdue: bool,
+
+    /// Run the jobs defined in a TOML config file concurrently instead of a single command, the
+    /// options given on the command line override the ones of every job
+    #[clap(long)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+
+    /// Print the effective configuration as a TOML config file usable with --config and exit
+    #[clap(long)]
+    #[serde(skip)]
+    dump_config: bool,
+
+    /// Check every job of --config with the same rules as when running it, print the problems
+    /// found and exit, with an error if there are any
+    #[clap(long, requires = "config")]
+    #[serde(skip)]
+    validate_config: bool,
+
+    /// Restart the jobs of --config with its new content when the file changes, after their
+    /// current execution. An invalid new content is reported and the running jobs are kept
+    #[clap(long, requires = "config")]
+    #[serde(skip)]
+    watch_config: bool,
+
+    /// Print a calendar of the executions in the next 7 days, one column per day and one row per
+    /// hour, and exit
+    #[clap(long)]
+    #[serde(skip)]
+    show_schedule_table: bool,
+
+    /// Print the next executions of the schedule and exit without running anything
+    #[clap(long)]
+    #[serde(skip)]
+    dry_run: bool,
+
+    /// Compare the executions of --dry-run with the plan saved in this file, print the times
+    /// removed and added, then save the new plan there
+    #[clap(long, requires = "dry_run")]
+    #[serde(skip)]
+    diff: Option<PathBuf>,
+
+    /// Lock file preventing another instance from running the same job, with --concurrent-safe
+    /// "{hash}" in the path is replaced by a hash of the command and the scheduled time. These
+    /// slot files are kept, clean their directory up from time to time
+    #[clap(long)]
+    lock_file: Option<PathBuf>,
+
+    /// Lock each scheduled execution instead of the whole process so that only one replica runs a
+    /// given slot (requires --lock-file)
+    #[clap(long, requires = "lock_file")]
+    concurrent_safe: bool,
+
+    /// Lock file holding cronthat's PID and an exclusive flock while it runs so that other
+    /// instances using the same path, possibly on other hosts of a shared filesystem, exit.
+    /// Taken over once its owner exits or crashes, which releases the flock (Unix only)
+    #[clap(long)]
+    pid_lock: Option<PathBuf>,
+
+    /// Report how late each execution started compared to its scheduled time
+    #[clap(long)]
+    since: bool,
+
+    /// Print a warning when an execution starts later than this after its scheduled time (e.g. 10s)
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    late_threshold: Option<Duration>,
+
+    /// Read the cron expression from the first line of this file that is neither blank nor a
+    /// comment starting with #. It is read again before waiting for each execution and on SIGHUP
+    /// so that it can be changed without restarting
+    #[clap(long, visible_alias = "cron-expression-file")]
+    #[serde(alias = "cron_expression_file")]
+    schedule_file: Option<PathBuf>,
+
+    /// Run at this time, HH:MM or HH:MM:SS, instead of following a cron expression. Defaults to
+    /// midnight with --days, --months or --weekdays
+    #[clap(long, value_name = "TIME", conflicts_with = "schedule_file")]
+    at_time: Option<String>,
+
+    /// Only run on these days of the month, e.g. "1,15" or "1-7", instead of following a cron
+    /// expression
+    #[clap(long, conflicts_with = "schedule_file")]
+    days: Option<String>,
+
+    /// Only run during these months, e.g. "jan-mar,dec", instead of following a cron expression
+    #[clap(long, conflicts_with = "schedule_file")]
+    months: Option<String>,
+
+    /// Only run on these days of the week, e.g. "mon-fri" or "saturday,sunday", instead of
+    /// following a cron expression
+    #[clap(long, conflicts_with = "schedule_file")]
+    weekdays: Option<String>,
+
+    /// Refuse schedules running more often than this, e.g. 10s to catch a mistyped "* * * * * *"
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    min_interval: Option<Duration>,
+
+    /// Shift every execution of the schedule by a duration, e.g. "5m" or "-30s"
+    #[clap(long, value_parser = parse_duration, allow_hyphen_values = true)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    schedule_offset: Option<Duration>,
+
+    /// Wait this long before the first execution, including the one of --now, e.g. to stagger
+    /// instances started at the same time
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    startup_delay: Option<Duration>,
+
+    /// Wait a random duration up to this one before the first execution, after --startup-delay,
+    /// e.g. to spread the load of instances deployed at the same time
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    startup_splay: Option<Duration>,
+
+    /// Seed of the random durations, e.g. of --startup-splay, to reproduce them
+    #[clap(long)]
+    seed: Option<u64>,
+
+    /// Let the first execution happen right away if the current second matches the schedule,
+    /// by default the first one is the next match strictly after now
+    #[clap(long)]
+    schedule_from_now: bool,
+
+    /// Time zone the cron expression is evaluated in, e.g. UTC or Europe/Paris. Times are still
+    /// printed and --until compared in local time
+    #[clap(long)]
+    cron_timezone: Option<Tz>,
+
+    /// Report the CPU time and max RSS of the command after each execution (Unix only)
+    #[clap(long)]
+    report_usage: bool,
+
+    /// Stop with an error once cronthat itself uses more than this many bytes of memory, checked
+    /// every second (the peak usage is checked on Unix systems other than Linux)
+    #[clap(long)]
+    max_memory: Option<u64>,
+
+    /// Number of times a failed execution is retried immediately
+    #[clap(long, default_value_t = 0)]
+    retry: usize,
+
+    /// Whether the --retry budget is reset on each tick or shared by the whole run
+    #[clap(long, value_enum, default_value_t = RetryScope::Tick)]
+    retry_scope: RetryScope,
+
+    /// Only retry executions exiting with one of these codes (e.g. 1,75), other failures are not
+    /// retried
+    #[clap(long, value_delimiter = ',', allow_hyphen_values = true)]
+    retry_codes: Vec<i32>,
+
+    /// Stop retrying a tick once this much time passed since its first attempt (e.g. 5m), retries
+    /// also stop when --until is reached
+    #[clap(long, value_parser = parse_duration)]
+    #[serde(
+        serialize_with = "duration::serialize_optional",
+        deserialize_with = "duration::deserialize_optional"
+    )]
+    retry_max_elapsed: Option<Duration>,
+
+    /// File keeping track of the cumulative execution time in milliseconds across runs
+    #[clap(long)]
+    budget_file: Option<PathBuf>,
+
+    /// Stop once the cumulative execution time in --budget-file exceeds this many milliseconds
+    #[clap(long, requires = "budget_file")]
+    budget_max_ms: Option<u64>,
+
+    /// Push the metrics of each execution to this Prometheus Pushgateway (http:// only)
+    #[clap(long)]
+    pushgateway: Option<String>,
+
+    /// Job name the metrics are pushed under, defaults to "cronthat"
+    #[clap(long, requires = "pushgateway")]
+    job_name: Option<String>,
+
+    /// Serve the status endpoint on this address, e.g. 127.0.0.1:9090. `/logs` returns the last
+    /// lines of the command's output, updated once each execution finishes
+    #[clap(long)]
+    status_addr: Option<String>,
+
+    /// Number of output lines kept for the `/logs` status endpoint
+    #[clap(long, default_value_t = default_log_buffer_lines())]
+    #[serde(default = "default_log_buffer_lines")]
+    log_buffer_lines: usize,
+
+    /// Metadata attached to the events sent by cronthat, e.g. env=production, Pushgateway metrics
+    /// are grouped by these labels (can be repeated)
+    #[clap(long)]
+    tag: Vec<Tag>,
+
+    /// Set an environment variable of the command to the trimmed content of a file read before
+    /// each execution, written NAME=PATH, e.g. DB_PASSWORD=/run/secrets/db_password (can be
+    /// repeated)
+    #[clap(long)]
+    env_from_file: Vec<EnvFile>,
+
+    /// Leave the --env-from-file variables whose file is missing unset instead of failing
+    #[clap(long)]
+    optional_secrets: bool,
+
+    /// Reject commands longer than this many bytes (0 means unlimited)
+    #[clap(long, default_value_t = 0)]
+    max_command_length: usize,
+
+    /// Parse the command's output as JSON, the execution fails if it is not valid JSON
+    #[clap(long)]
+    json_command_output: bool,
+
+    /// Fail the execution unless the command's JSON output has this field value, e.g. status=ok
+    /// (implies --json-command-output, can be repeated)
+    #[clap(long)]
+    expect_json_field: Vec<JsonField>,
+
+    /// Fail the execution unless the command's output matches this regex (can be repeated, all
+    /// of them must match)
+    #[clap(long)]
+    #[serde(
+        serialize_with = "output::serialize_regexes",
+        deserialize_with = "output::deserialize_regexes"
+    )]
+    expect_output_regex: Vec<Regex>,
+
+    /// Fail the execution if the command's output matches this regex, e.g. ERROR (can be
+    /// repeated)
+    #[clap(long)]
+    #[serde(
+        serialize_with = "output::serialize_regexes",
+        deserialize_with = "output::deserialize_regexes"
+    )]
+    fail_output_regex: Vec<Regex>,
+
+    /// How captured output is forwarded while the command runs
+    #[clap(long, value_enum, default_value_t = FlushMode::Line)]
+    flush_mode: FlushMode,
+
+    /// Encoding of the command's output, e.g. "latin1" or "shift_jis", converted to UTF-8 before
+    /// being printed or checked (passed through as is by default)
+    #[clap(long, value_name = "ENCODING")]
+    output_encoding: Option<OutputEncoding>,
+
+    /// Only print the first lines of the command's output, followed by "... (truncated)"
+    #[clap(long)]
+    max_output_lines: Option<usize>,
+
+    /// Hold the command's output back and only print its last lines when the execution fails
+    #[clap(long, value_name = "LINES")]
+    tail_on_failure: Option<usize>,
+
+    /// Command run once at startup whose trimmed output starts every line of the command's
+    /// output, e.g. "git rev-parse --short HEAD"
+    #[clap(long)]
+    output_prefix_command: Option<String>,
+
+    /// Expose the trimmed output of the previous execution to the next one in
+    /// CRONTHAT_PREV_OUTPUT, e.g. to continue from a cursor it printed. Only its first 32 KiB are
+    /// kept
+    #[clap(long)]
+    command_output_to_env: bool,
+
+    /// Print the command as run, after the substitutions, before each execution (pipeline stages
+    /// are joined by "|"), also included in the lines of --json-runs
+    #[clap(long)]
+    echo_command: bool,
+
+    /// Print this line after each successful execution, "{hostname}" is replaced with the host
+    /// name and the placeholders of --stdin-template with the current time
+    #[clap(long, value_name = "TEXT")]
+    success_message: Option<String>,
+
+    /// Print this line instead of the warning after each failed execution, or fail with it with
+    /// --stop-on-error, with the same placeholders as --success-message
+    #[clap(long, value_name = "TEXT")]
+    failure_message: Option<String>,
+
+    /// Print one JSON line per execution with its exit code, duration and base64-encoded stdout
+    /// and stderr instead of the output, e.g. with --quiet to only print these lines
+    #[clap(long)]
+    json_runs: bool,
+
+    /// Hash the command's output and only print it when it differs from the previous execution's
+    #[clap(long)]
+    hash_output: bool,
+
+    /// Command run when the output's hash differs from the previous execution's (implies
+    /// --hash-output), with CRONTHAT_OUTPUT_HASH and CRONTHAT_PREVIOUS_OUTPUT_HASH set. Its exit
+    /// code is only logged
+    #[clap(long)]
+    on_change: Option<String>,
+
+    /// Keep the hash of the last output in this file to detect changes across restarts (implies
+    /// --hash-output)
+    #[clap(long)]
+    change_state_file: Option<PathBuf>,
+
+    /// Stop scheduling executions once this file exists
+    #[clap(long)]
+    stop_file: Option<PathBuf>,
+
+    /// Stop scheduling executions once this file contains the regular expression, written
+    /// PATH:PATTERN, e.g. "/tmp/pipeline.log:(?m)^DONE$"
+    #[clap(long)]
+    until_file_contains: Option<Marker>,
+
+    /// Delete the --stop-file when it stops the schedule
+    #[clap(long, requires = "stop_file")]
+    remove_stop_file: bool,
+
+    /// Command run before waiting for each execution, stop if it exits with status 0
+    #[clap(long)]
+    stop_if: Option<String>,
+
+    /// Command run before waiting for each execution, stop unless it exits with status 0
+    #[clap(long)]
+    continue_if: Option<String>,
+
+    /// Run --stop-if and --continue-if once at startup and fail if they cannot be run at all
+    #[clap(long)]
+    pre_validate_guards: bool,
+
+    /// Run the command with an empty environment, only the variables set by cronthat and the ones
+    /// matching --pass-env-prefix are kept
+    #[clap(long)]
+    clean_env: bool,
+
+    /// Forward the environment variables starting with this prefix, e.g. AWS_ (implies
+    /// --clean-env, can be repeated)
+    #[clap(long)]
+    pass_env_prefix: Vec<String>,
+
+    /// Replace $VAR and ${VAR} in the command with the value of cronthat's environment variables
+    /// before running it, whatever the shell. Fails if a variable is not set
+    #[clap(long)]
+    expand_env: bool,
+
+    /// Replace the variables that are not set with an empty string with --expand-env
+    #[clap(long, requires = "expand_env")]
+    allow_unset_env: bool,
+
+    /// Options given on the command line, applied to every job of --config.
+    #[clap(skip)]
+    #[serde(skip)]
+    overrides: Vec<String>,
+}
+
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RetryScope {
+    /// Each tick can use the whole retry budget
+    #[default]
+    Tick,
+    /// The retry budget is shared by all ticks
+    Global,
+}
+
+/// How --repetitions and --until are combined.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StopWhen {
+    /// Stop as soon as one of them is reached
+    #[default]
+    Any,
+    /// Stop once both are reached
+    All,
+}
+
+static PIPELINE_SEPARATOR: &str = ":::";
+/// Substituted in the command with the number of the execution.
+static ITERATION_PLACEHOLDER: &str = "{iteration}";
+/// Exit code of shells when they cannot find a command.
+static COMMAND_NOT_FOUND_STATUS: i32 = 127;
+/// Exit code of ssh when it cannot connect or its connection fails.
+static SSH_ERROR_STATUS: i32 = 255;
+/// Exit code of executions killed by a timeout, the same as `timeout(1)`.
+static TIMEOUT_STATUS: i32 = 124;
+/// Number of upcoming executions looked at to find the smallest interval of a schedule.
+static MIN_INTERVAL_SAMPLES: usize = 100;
+/// Number of bytes of output kept by --command-output-to-env.
+static PREV_OUTPUT_MAX_BYTES: usize = 32 * 1024;
+/// Number of upcoming executions printed by --dry-run.
+static DRY_RUN_EXECUTIONS: usize = 10;
+/// How often --watch-config checks whether the config file changed.
+static WATCH_CONFIG_INTERVAL: std::time::Duration = std::time::Duration::from_millis(500);
+/// How often the timeout watchdog of an execution checks for a shutdown.
+static WATCHDOG_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+/// Default of --log-buffer-lines, also used for the config jobs that leave it out.
+fn default_log_buffer_lines() -> usize {
+    100
+}
+
+/// `value` as a single word for sh.
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', r"'\''"))
+}
+
+fn shell_command(script: &str) -> Command {
+    let mut command = if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.arg("/C");
+        command
+    } else {
+        let mut command = Command::new("sh");
+        command.arg("-c");
+        command
+    };
+    command.arg(script);
+    command
+}
+
+/// Trimmed `output` cut to `PREV_OUTPUT_MAX_BYTES` for --command-output-to-env.
+fn previous_output(output: &str) -> String {
+    let output = output.trim();
+    if output.len() <= PREV_OUTPUT_MAX_BYTES {
+        return output.to_string();
+    }
+    let mut end = PREV_OUTPUT_MAX_BYTES;
+    while !output.is_char_boundary(end) {
+        end -= 1;
+    }
+    warning!(
+        "warning: command output is {} bytes long, only the first {} are kept in \
+         CRONTHAT_PREV_OUTPUT",
+        output.len(),
+        end
+    );
+    output[..end].to_string()
+}
+
+/// Canonical `path` for --follow-symlinks, a file that doesn't exist yet is resolved through its
+/// directory.
+fn canonical_path(path: &Path) -> Result<PathBuf> {
+    if path.exists() {
+        return fs::canonicalize(path).with_context(|| format!("cannot resolve {:?}", path));
+    }
+    let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+        return Ok(path.to_path_buf());
+    };
+    let parent = match parent.as_os_str().is_empty() {
+        true => Path::new("."),
+        false => parent,
+    };
+    let parent = fs::canonicalize(parent).with_context(|| format!("cannot resolve {:?}", path))?;
+    Ok(parent.join(name))
+}
+
+/// Hex SHA-256 of an execution's output for --hash-output.
+fn output_hash(output: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(output))
+}
+
+/// Hash kept in --change-state-file, none until it is first written.
+fn read_output_hash(path: &Path) -> Result<Option<String>> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    let hash = fs::read_to_string(path)
+        .with_context(|| format!("cannot read change state file {:?}", path))?;
+    Ok(Some(hash.trim().to_string()))
+}
+
+/// Trimmed output of --output-prefix-command.
+fn output_prefix(command: &str) -> Result<String> {
+    let output = shell_command(command)
+        .stderr(Stdio::inherit())
+        .output()
+        .with_context(|| format!("cannot spawn --output-prefix-command '{}'", command))?;
+    if !output.status.success() {
+        bail!(
+            "--output-prefix-command '{}' exited with status {}",
+            command,
+            output.status.code().unwrap_or(1)
+        );
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Replace the time placeholders of `template` with `datetime`.
+fn expand_template(template: &str, datetime: &DateTime<Local>) -> String {
+    template
+        .replace("{scheduled}", &datetime.to_rfc3339())
+        .replace("{timestamp}", &datetime.timestamp().to_string())
+        .replace("{date}", &datetime.format("%Y-%m-%d").to_string())
+        .replace("{time}", &datetime.format("%H:%M:%S").to_string())
+}
+
+/// When a timeout of `timeout` set by `option` starting now expires.
+fn deadline_after(timeout: Duration, option: &str) -> Result<DateTime<Local>> {
+    Local::now()
+        .checked_add_signed(timeout)
+        .with_context(|| format!("{} {} is too long", option, format_duration(timeout)))
+}
+
+/// Replace "{hostname}" in `message` with the host name and the time placeholders with now.
+fn expand_message(message: &str) -> String {
+    let hostname = system_hostname()
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| fs::read_to_string("/etc/hostname").ok())
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_default();
+    expand_template(message, &Local::now()).replace("{hostname}", &hostname)
+}
+
+#[cfg(unix)]
+fn system_hostname() -> Option<String> {
+    let mut name = [0u8; 256];
+    // SAFETY: gethostname writes at most `name.len()` bytes to the buffer.
+    if unsafe { libc::gethostname(name.as_mut_ptr().cast(), name.len()) } != 0 {
+        return None;
+    }
+    let len = name
+        .iter()
+        .position(|&byte| byte == 0)
+        .unwrap_or(name.len());
+    Some(String::from_utf8_lossy(&name[..len]).into_owned())
+}
+
+#[cfg(not(unix))]
+fn system_hostname() -> Option<String> {
+    std::env::var("COMPUTERNAME").ok()
+}
+
+/// Replace `$VAR` and `${VAR}` in `command` with the value of the environment variable, unset
+/// variables are replaced with an empty string if `allow_unset`, otherwise they are an error.
+fn expand_env(command: &str, allow_unset: bool) -> Result<String> {
+    static VARIABLE: OnceLock<Regex> = OnceLock::new();
+    let variable = VARIABLE.get_or_init(|| {
+        Regex::new(r"\$(?:\{([A-Za-z_][A-Za-z0-9_]*)\}|([A-Za-z_][A-Za-z0-9_]*))").unwrap()
+    });
+    let mut expanded = String::with_capacity(command.len());
+    let mut last = 0;
+    for captures in variable.captures_iter(command) {
+        let whole = captures.get(0).unwrap();
+        let name = captures.get(1).or(captures.get(2)).unwrap().as_str();
+        let value = match std::env::var(name) {
+            Ok(value) => value,
+            Err(_) if allow_unset => String::new(),
+            Err(_) => bail!(
+                "environment variable {} is not set, add --allow-unset-env to replace it with \
+                 an empty string",
+                name
+            ),
+        };
+        expanded.push_str(&command[last..whole.start()]);
+        expanded.push_str(&value);
+        last = whole.end();
+    }
+    expanded.push_str(&command[last..]);
+    Ok(expanded)
+}
+
+/// First program of a shell script if it cannot be found, either as a path or in `PATH`.
+fn missing_program(script: &str) -> Option<String> {
+    // Skip environment assignments such as `FOO=bar command`.
+    let program = script.split_whitespace().find(|word| !word.contains('='))?;
+    let found = if program.contains('/') {
+        Path::new(program).exists()
+    } else {
+        std::env::var_os("PATH").is_some_and(|paths| {
+            std::env::split_paths(&paths).any(|dir| dir.join(program).is_file())
+        })
+    };
+    (!found).then(|| program.to_string())
+}
+
+#[cfg(unix)]
+fn kill_process_group(pid: u32) {
+    // SAFETY: kill has no memory safety requirements.
+    unsafe { libc::kill(-(pid as i32), libc::SIGKILL) };
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(pid: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid.to_string()])
+        .status();
+}
+
+/// Send SIGINT to the process group of `pid`, which doesn't get the terminal's CTRL-C.
+#[cfg(unix)]
+fn interrupt_process_group(pid: u32) {
+    // SAFETY: kill has no memory safety requirements.
+    unsafe { libc::kill(-(pid as i32), libc::SIGINT) };
+}
+
+#[cfg(not(unix))]
+fn interrupt_process_group(_pid: u32) {
+    // Without process groups, the command gets CTRL-C from the console itself.
+}
+
+fn started_late(late: Duration) -> String {
+    format!(
+        "started {} late",
+        format_duration(late.max(Duration::zero()))
+    )
+}
+
+fn parse_date_time(value: &str) -> Result<DateTime<Local>> {
+    let dt = NaiveDateTime::parse_from_str(value, DATETIME_FORMAT)?
+        .and_local_timezone(Local::now().timezone())
+        .single()
+        .context("cannot parse with timezone")?;
+    Ok(dt)
+}
+
+/// Output of the last stage captured by `run_stages`, and the resource usage of all the stages.
+#[derive(Default)]
+struct Captured {
+    stdout: Vec<u8>,
+    /// Only captured with --json-runs.
+    stderr: Vec<u8>,
+    usage: Usage,
+}
+
+/// State of the scheduling loop that only lives as long as the process, unlike [State].
+#[derive(Default)]
+struct RunContext {
+    /// Retries left with `--retry-scope global`.
+    global_retries: usize,
+    /// Exit code of the previous execution, exposed as `CRONTHAT_LAST_EXIT_CODE`.
+    last_exit_code: Option<i32>,
+    /// Cumulative execution time, including the one read from `--budget-file`.
+    spent: Duration,
+    /// Duration of the previous execution.
+    last_duration: Duration,
+    /// When the last successful execution finished.
+    last_success: Option<DateTime<Local>>,
+    /// Number of successful executions since the start, for --fail-if-no-executions.
+    successes: usize,
+    /// Number of executions so far, retries included.
+    executions: usize,
+    /// Trace ID of the current execution, exposed as `CRONTHAT_TRACE_ID`.
+    trace_id: Option<String>,
+    /// Program the last execution failed to find.
+    missing_program: Option<String>,
+    /// Output kept for the status endpoint.
+    log_buffer: Option<LogBuffer>,
+    /// Executions coalesced by --batch-window since the last run.
+    coalesced_ticks: usize,
+    /// Executions done, retries excluded, to pick the --cycle command.
+    cycles: usize,
+    /// When the last execution finished, for --dedup-window.
+    last_finished: Option<DateTime<Local>>,
+    /// Command run by the last execution.
+    last_command: String,
+    /// Output of the previous execution, exposed as `CRONTHAT_PREV_OUTPUT`.
+    previous_output: Option<String>,
+    /// Output of --output-prefix-command.
+    output_prefix: Option<String>,
+    /// SHA-256 of the previous execution's output with --hash-output.
+    output_hash: Option<String>,
+    /// Shutdown of the scheduling loop, to forward the captured output held back when triggered.
+    shutdown: Shutdown,
+    /// When --timeout-total is reached.
+    total_deadline: Option<DateTime<Local>>,
+    /// Executions and next tick shown by --tui.
+    dashboard: Option<Dashboard>,
+}
+
+impl CronThat {
+    /// Parse `args` like `Parser::try_parse_from`, also recording the options they set
+    /// explicitly for --config.
+    pub fn try_parse_args<I, T>(args: I) -> Result<CronThat, clap::Error>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let matches = CronThat::command().try_get_matches_from(args)?;
+        let mut cronthat = CronThat::from_arg_matches(&matches)?;
+        cronthat.overrides = matches
+            .ids()
+            .filter(|id| {
+                matches!(
+                    matches.value_source(id.as_str()),
+                    Some(ValueSource::CommandLine | ValueSource::EnvVariable)
+                )
+            })
+            .map(|id| id.to_string())
+            .collect();
+        Ok(cronthat)
+    }
+
+    pub fn execute(&self) -> Result<()> {
+        log::set_level(match self.quiet {
+            true => LogLevel::Error,
+            false => self.log_level,
+        });
+        if self.follow_symlinks {
+            let mut resolved = self.clone();
+            resolved.resolve_symlinks()?;
+            return resolved.start();
+        }
+        self.start()
+    }
+
+    /// Detach from the terminal with --daemon, then run.
+    fn start(&self) -> Result<()> {
+        if self.daemon {
+            // Report invalid arguments to the terminal before detaching from it.
+            if self.config.is_none() {
+                self.check_args()?;
+            }
+            match daemon::daemonize(self.log_file.as_deref(), self.pid_file.as_deref())? {
+                Fork::Parent => return Ok(()),
+                Fork::Daemon => {
+                    // The daemon must never return into its parent's code.
+                    if let Err(err) = self.run_with_tee() {
+                        error!("{:?}", err);
+                        std::process::exit(1);
+                    }
+                    std::process::exit(0);
+                }
+            }
+        }
+        self.run_with_tee()?;
+        Ok(())
+    }
+
+    fn run_with_tee(&self) -> Result<State> {
+        let tee = tee::start(&self.tee)?;
+        match self.run(Shutdown::on_ctrl_c()) {
+            // Printed while the output is still copied, so that the --tee files get it too.
+            Err(err) if tee.is_some() => {
+                error!("{:?}", err.context("Something went wrong"));
+                drop(tee);
+                std::process::exit(1);
+            }
+            result => result,
+        }
+    }
+
+    /// Run the schedule until a stop condition is met or `shutdown` is triggered and return the
+    /// final state.
+    pub fn run(&self, shutdown: &Shutdown) -> Result<State> {
+        if self.dump_config {
+            print!("{}", self.dump_config()?);
+            return Ok(State::default());
+        }
+
+        if let Some(config) = &self.config {
+            if self.validate_config {
+                self.validate_config(config)?;
+                return Ok(State::default());
+            }
+            return self.run_jobs(config, shutdown);
+        }
+
+        if let Some(first_word) = self
+            .cron_expression
+            .as_ref()
+            .filter(|_| self.schedule_file.is_some() || self.composes_schedule())
+        {
+            // Without a cron expression, the first positional argument is part of the command.
+            let mut cronthat = self.clone();
+            cronthat.command.insert(0, first_word.clone());
+            cronthat.cron_expression = None;
+            return cronthat.run(shutdown);
+        }
+
+        if self.dry_run {
+            let schedule = schedule::parse(&self.read_cron_expression()?)?;
+            self.dry_run(&schedule)?;
+            return Ok(State::default());
+        }
+
+        if self.show_schedule_table {
+            let schedule = schedule::parse(&self.read_cron_expression()?)?;
+            println!(
+                "{}",
+                schedule::week_table(&schedule, self.cron_timezone, Local::now())
+            );
+            return Ok(State::default());
+        }
+
+        let (mut cron_expression, mut schedule) = self.validate()?;
+        if self.pre_validate_guards {
+            self.validate_guards()?;
+        }
+        if self.confirm && !self.confirmed(&cron_expression)? {
+            bail!("execution not confirmed");
+        }
+
+        let _lock = match &self.lock_file {
+            Some(path) if !self.concurrent_safe => Some(LockFile::acquire(path)?),
+            _ => None,
+        };
+        let _pid_lock = self.pid_lock.as_deref().map(PidLock::acquire).transpose()?;
+
+        let mut state = match &self.state_file {
+            Some(path) => State::load(path)?,
+            None => State::default(),
+        };
+
+        let mut context = RunContext {
+            global_retries: self.retry,
+            spent: match &self.budget_file {
+                Some(path) => budget::read_used(path)?,
+                None => Duration::zero(),
+            },
+            last_success: state.last_success,
+            log_buffer: self.serve_status()?,
+            total_deadline: self
+                .timeout_total
+                .map(|timeout| deadline_after(timeout, "--timeout-total"))
+                .transpose()?,
+            shutdown: shutdown.clone(),
+            output_prefix: self
+                .output_prefix_command
+                .as_deref()
+                .map(output_prefix)
+                .transpose()?,
+            output_hash: self
+                .change_state_file
+                .as_deref()
+                .map(read_output_hash)
+                .transpose()?
+                .flatten(),
+            ..Default::default()
+        };
+        if self.budget_exhausted(&context) {
+            return Ok(state);
+        }
+        let tui = if self.tui {
+            tui::start(self.time_format())?
+        } else {
+            None
+        };
+        context.dashboard = tui.as_ref().map(|tui| tui.dashboard.clone());
+
+        let holidays = match &self.holidays {
+            Some(path) => calendar::read_holidays(path)?,
+            None => HashSet::new(),
+        };
+
+        let memory_limit = self
+            .max_memory
+            .map(|max_bytes| MemoryLimit::watch(max_bytes, shutdown));
+
+        if let Some(delay) = self.startup_delay {
+            info!(
+                "{} -- Waiting {} before starting",
+                self.log_time(),
+                format_duration(delay)
+            );
+            if shutdown.sleep(delay.to_std()?) {
+                return Ok(state);
+            }
+        }
+        if let Some(window) = self.startup_splay {
+            let splay = self.splay(window);
+            info!(
+                "{} -- Waiting {} before starting (--startup-splay {})",
+                self.log_time(),
+                format_duration(splay),
+                format_duration(window)
+            );
+            if shutdown.sleep(splay.to_std()?) {
+                return Ok(state);
+            }
+        }
+
+        if self.now
+            || self.missed_run(&state)
+            || self.overdue_run(&state, &schedule)
+            || schedule::is_reboot(&cron_expression)
+        {
+            let status = self.spawn_with_retries(None, &mut context)?;
+            // The immediate execution counts toward --repetitions.
+            state.runs += 1;
+            self.record(&mut state, &mut context, self.succeeded(status))?;
+            if self.run_until_code_reached(status, state.runs) {
+                return Ok(state);
+            }
+            if !self.succeeded(status) {
+                self.handle_failure(&context)?;
+            }
+        }
+
+        let control = match &self.control_fifo {
+            Some(path) => Some(Control::listen(path, shutdown)?),
+            None if self.run_now_on_signal || self.reload_on_signal() => Some(Control::default()),
+            None => None,
+        };
+        if let Some(control) = control.as_ref().filter(|_| self.run_now_on_signal) {
+            control.run_now_on_signal()?;
+        }
+        if let Some(control) = control.as_ref().filter(|_| self.reload_on_signal()) {
+            control.reload_on_signal()?;
+        }
+        let mut previous = None;
+        let mut skipped = 0;
+        let mut skip_streak = 0;
+        let mut ticks = 0;
+        let mut last_fired: Option<DateTime<Local>> = None;
+        loop {
+            if self.reload_schedule(&mut cron_expression, &mut schedule) {
+                previous = None;
+            }
+            let Some(datetime) = self.next_fire_time(&schedule, previous.as_ref()) else {
+                if self.loop_forever {
+                    bail!("the schedule has no upcoming execution left (--loop-forever)");
+                }
+                info!(
+                    "{} -- The schedule has no upcoming execution left, stopping",
+                    self.log_time()
+                );
+                break;
+            };
+            let previous_tick = previous.replace(datetime);
+
+            if shutdown.is_triggered()
+                || self.must_stop(state.runs)
+                || self.budget_exhausted(&context)
+                || self.stop_file_found()?
+                || self.marker_found()?
+                || self.stop_condition_met()?
+            {
+                break;
+            }
+            if context
+                .total_deadline
+                .is_some_and(|deadline| datetime > deadline)
+            {
+                info!(
+                    "{} -- Next execution at {} is past --timeout-total, stopping",
+                    self.log_time(),
+                    self.format_time(&datetime)
+                );
+                break;
+            }
+
+            if let Some(dashboard) = &context.dashboard {
+                dashboard.scheduled(datetime);
+            }
+            debug!(
+                "{} -- Next execution at {}",
+                self.log_time(),
+                self.format_time(&datetime)
+            );
+            let now: DateTime<Local> = Local::now();
+            if let Some(offset) = self.schedule_offset {
+                info!(
+                    "{} -- Next execution at {} (offset {})",
+                    self.format_time(&now),
+                    self.format_time(&datetime),
+                    format_duration(offset)
+                );
+            }
+            let wait = datetime.signed_duration_since(now);
+            if self.explain && wait > Duration::zero() {
+                info!("{}", self.explain_wait(wait, &datetime, state.runs + 1));
+            }
+            if wait > Duration::zero() {
+                match self.sleep(wait, shutdown, control.as_ref())? {
+                    Wake::Elapsed => {}
+                    Wake::Shutdown => break,
+                    Wake::RunNow => {
+                        info!("{} -- Manual execution requested", self.log_time());
+                        skip_streak = 0;
+                        let status = self.spawn_with_retries(None, &mut context)?;
+                        if !self.exclude_manual_runs {
+                            state.runs += 1;
+                        }
+                        self.record(&mut state, &mut context, self.succeeded(status))?;
+                        if self.run_until_code_reached(status, state.runs) {
+                            break;
+                        }
+                        if !self.succeeded(status) {
+                            self.handle_failure(&context)?;
+                        }
+                        previous = previous_tick;
+                        continue;
+                    }
+                    Wake::Reload => {
+                        previous = previous_tick;
+                        continue;
+                    }
+                }
+            }
+            if self.stop_file_found()? || self.marker_found()? {
+                break;
+            }
+            if control.as_ref().is_some_and(Control::is_paused) {
+                info!(
+                    "{} -- Skipping execution scheduled at {}, paused",
+                    self.log_time(),
+                    self.format_time(&datetime)
+                );
+                self.count_skip(&mut skip_streak)?;
+                continue;
+            }
+            if let Some(reason) =
+                calendar::day_off(datetime.date_naive(), self.business_days_only, &holidays)
+            {
+                info!(
+                    "{} -- Skipping execution scheduled at {}, {}",
+                    self.log_time(),
+                    self.format_time(&datetime),
+                    reason
+                );
+                self.count_skip(&mut skip_streak)?;
+                continue;
+            }
+            if skipped < self.skip_first {
+                skipped += 1;
+                info!(
+                    "{} -- Skipping execution scheduled at {} ({} of {} skipped with --skip-first)",
+                    self.log_time(),
+                    self.format_time(&datetime),
+                    skipped,
+                    self.skip_first
+                );
+                continue;
+            }
+            ticks += 1;
+            if let Some(every_nth) = self.every_nth.filter(|every_nth| ticks % every_nth != 0) {
+                info!(
+                    "{} -- Skipping execution scheduled at {} (--every-nth {})",
+                    self.log_time(),
+                    self.format_time(&datetime),
+                    every_nth
+                );
+                self.count_skip(&mut skip_streak)?;
+                continue;
+            }
+            if let (Some(window), Some(last_finished)) = (self.dedup_window, context.last_finished)
+            {
+                if Local::now() - last_finished < window {
+                    warning!(
+                        "warning: skipping execution scheduled at {}, the previous one finished \
+                         less than --dedup-window {} ago",
+                        self.format_time(&datetime),
+                        format_duration(window)
+                    );
+                    self.count_skip(&mut skip_streak)?;
+                    continue;
+                }
+            }
+            if let (Some(window), Some(last_fired)) = (self.batch_window, last_fired) {
+                if datetime - last_fired < window {
+                    context.coalesced_ticks += 1;
+                    info!(
+                        "{} -- Coalescing execution scheduled at {} ({} within --batch-window {})",
+                        self.log_time(),
+                        self.format_time(&datetime),
+                        context.coalesced_ticks,
+                        format_duration(window)
+                    );
+                    continue;
+                }
+            }
+            if !self.claim_slot(&datetime)? {
+                info!(
+                    "{} -- Skipping execution scheduled at {}, already claimed by another instance",
+                    self.log_time(),
+                    self.format_time(&datetime)
+                );
+                self.count_skip(&mut skip_streak)?;
+                continue;
+            }
+            last_fired = Some(datetime);
+            skip_streak = 0;
+            let status = self.spawn_with_retries(Some(&datetime), &mut context)?;
+            let succeeded = self.succeeded(status);
+            context.coalesced_ticks = 0;
+            state.runs += 1;
+            self.record(&mut state, &mut context, succeeded)?;
+            if self.assert_no_overlap {
+                self.check_overlap(&schedule, &datetime, &context)?;
+            }
+            if self.run_until_code_reached(status, state.runs) {
+                break;
+            }
+            if !succeeded {
+                self.handle_failure(&context)?;
+            }
+        }
+
+        if memory_limit.as_ref().is_some_and(MemoryLimit::exceeded) {
+            bail!("memory usage exceeded --max-memory");
+        }
+        if self.fail_if_no_executions && context.successes == 0 {
+            bail!("no successful executions before deadline");
+        }
+        Ok(state)
+    }
+
+    /// Report a failed execution, or stop with --stop-on-error.
+    fn handle_failure(&self, context: &RunContext) -> Result<()> {
+        if self.stop_on_error {
+            if let Some(program) = &context.missing_program {
+                bail!(
+                    "command '{}' not found in PATH, check its spelling or install it",
+                    program
+                );
+            }
+            bail!("{}", self.failure_reason(context));
+        } else if let Some(message) = &self.failure_message {
+            warning!("{}", expand_message(message));
+            warning!();
+        } else {
+            warning!("warning: {}", self.failure_reason(context));
+            warning!();
+        }
+        Ok(())
+    }
+
+    /// Fail if the execution scheduled at `scheduled` finished after the next scheduled one.
+    fn check_overlap(
+        &self,
+        schedule: &Schedule,
+        scheduled: &DateTime<Local>,
+        context: &RunContext,
+    ) -> Result<()> {
+        let (Some(next), Some(finished)) = (
+            self.next_fire_time(schedule, Some(scheduled)),
+            context.last_finished,
+        ) else {
+            return Ok(());
+        };
+        if finished > next {
+            bail!(
+                "execution scheduled at {} was still running when the next one was scheduled at \
+                 {}, overran by {} (--assert-no-overlap)",
+                self.format_time(scheduled),
+                self.format_time(&next),
+                format_duration(finished - next)
+            );
+        }
+        Ok(())
+    }
+
+    /// Count a skipped execution in `streak`, failing once --max-consecutive-skips executions in a
+    /// row were skipped.
+    fn count_skip(&self, streak: &mut usize) -> Result<()> {
+        *streak += 1;
+        if let Some(max_skips) = self.max_consecutive_skips.filter(|max| *streak >= *max) {
+            bail!(
+                "{} executions in a row were skipped (--max-consecutive-skips {}), check the \
+                 options filtering them",
+                streak,
+                max_skips
+            );
+        }
+        Ok(())
+    }
+
+    /// Sleep for `wait`, interrupted by a shutdown or a --control-fifo command.
+    fn sleep(
+        &self,
+        wait: Duration,
+        shutdown: &Shutdown,
+        control: Option<&Control>,
+    ) -> Result<Wake> {
+        trace!("{} -- Sleeping {}", self.log_time(), format_duration(wait));
+        let wait = wait.to_std()?;
+        Ok(match control {
+            Some(control) => control.sleep(wait, shutdown),
+            None if shutdown.sleep(wait) => Wake::Shutdown,
+            None => Wake::Elapsed,
+        })
+    }
+
+    /// Next execution of the schedule after `previous` (or now) shifted by --schedule-offset.
+    /// Ticks are looked up from `previous - offset` so that shifted executions still ahead of us
+    /// are not skipped.
+    fn next_fire_time(
+        &self,
+        schedule: &Schedule,
+        previous: Option<&DateTime<Local>>,
+    ) -> Option<DateTime<Local>> {
+        let offset = self.schedule_offset.unwrap_or_else(Duration::zero);
+        let previous = previous
+            .copied()
+            .unwrap_or_else(|| self.schedule_start(Local::now()));
+        schedule::next_after(schedule, &(previous - offset), self.cron_timezone)
+            .map(|datetime| datetime + offset)
+    }
+
+    /// Print the next executions of `schedule`, or how they changed since the --diff plan.
+    fn dry_run(&self, schedule: &Schedule) -> Result<()> {
+        let mut plan = Vec::new();
+        let mut previous = None;
+        while plan.len() < DRY_RUN_EXECUTIONS {
+            let Some(datetime) = self.next_fire_time(schedule, previous.as_ref()) else {
+                break;
+            };
+            if self.stop_reached(plan.len(), datetime) {
+                break;
+            }
+            plan.push(datetime.fixed_offset());
+            previous = Some(datetime);
+        }
+
+        let Some(path) = &self.diff else {
+            plan.iter()
+                .for_each(|datetime| println!("{}", datetime.to_rfc3339()));
+            return Ok(());
+        };
+        let old_plan = if path.exists() {
+            fs::read_to_string(path)
+                .with_context(|| format!("cannot read plan file {:?}", path))?
+                .lines()
+                .map(|line| {
+                    DateTime::parse_from_rfc3339(line)
+                        .with_context(|| format!("invalid time {:?} in plan file {:?}", line, path))
+                })
+                .collect::<Result<Vec<_>>>()?
+        } else {
+            Vec::new()
+        };
+        println!("--- {}\n+++ new plan", path.display());
+        println!("{}", schedule::diff_plans(&old_plan, &plan));
+        let plan: Vec<String> = plan.iter().map(DateTime::to_rfc3339).collect();
+        let mut contents = plan.join("\n");
+        contents.push('\n');
+        write_atomically(path, contents)
+    }
+
+    /// Instant the first execution is looked up after. `Schedule::after` is exclusive and `now`
+    /// is rarely on a whole second, so by default a schedule matching the current second only
+    /// fires on its next match. With --schedule-from-now the lookup starts just before the
+    /// current second so that it fires right away.
+    fn schedule_start(&self, now: DateTime<Local>) -> DateTime<Local> {
+        if self.schedule_from_now {
+            now.trunc_subsecs(0) - Duration::seconds(1)
+        } else {
+            now
+        }
+    }
+
+    fn read_cron_expression(&self) -> Result<String> {
+        match &self.schedule_file {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("cannot read schedule file {:?}", path))?
+                .lines()
+                .map(str::trim)
+                .find(|line| !line.is_empty() && !line.starts_with('#'))
+                .map(str::to_string)
+                .with_context(|| format!("no cron expression in schedule file {:?}", path)),
+            None if self.composes_schedule() => schedule::compose(
+                self.at_time.as_deref(),
+                self.days.as_deref(),
+                self.months.as_deref(),
+                self.weekdays.as_deref(),
+            ),
+            None => self.cron_expression.clone().context("no cron expression"),
+        }
+    }
+
+    /// Whether the cron expression is made of --at-time, --days, --months and --weekdays.
+    fn composes_schedule(&self) -> bool {
+        self.at_time.is_some()
+            || self.days.is_some()
+            || self.months.is_some()
+            || self.weekdays.is_some()
+    }
+
+    /// Whether SIGHUP reads --schedule-file again right away.
+    fn reload_on_signal(&self) -> bool {
+        self.schedule_file.is_some() && cfg!(unix)
+    }
+
+    /// Read --schedule-file again and switch to its cron expression if it changed and is valid,
+    /// returns whether the schedule changed.
+    fn reload_schedule(&self, cron_expression: &mut String, schedule: &mut Schedule) -> bool {
+        if self.schedule_file.is_none() {
+            return false;
+        }
+        let new_expression = match self.read_cron_expression() {
+            Ok(new_expression) if new_expression == *cron_expression => return false,
+            Ok(new_expression) => new_expression,
+            Err(err) => {
+                warning!("warning: {:#}, keeping '{}'", err, cron_expression);
+                return false;
+            }
+        };
+        let new_schedule = schedule::parse(&new_expression).and_then(|new_schedule| {
+            self.check_min_interval(&new_schedule)?;
+            Ok(new_schedule)
+        });
+        match new_schedule {
+            Ok(new_schedule) => {
+                info!(
+                    "{} -- Schedule changed from '{}' to '{}'",
+                    self.log_time(),
+                    cron_expression,
+                    new_expression
+                );
+                *cron_expression = new_expression;
+                *schedule = new_schedule;
+                true
+            }
+            Err(err) => {
+                warning!(
+                    "warning: cannot use '{}' ({:#}), keeping '{}'",
+                    new_expression,
+                    err,
+                    cron_expression
+                );
+                false
+            }
+        }
+    }
+
+    /// Serialize the jobs of --config with the command line overrides applied, or this command
+    /// line as a single job, to TOML.
+    fn dump_config(&self) -> Result<String> {
+        let config = match &self.config {
+            Some(path) => self.load_config(path)?,
+            None => Config {
+                jobs: vec![Job {
+                    name: None,
+                    cronthat: self.clone(),
+                }],
+            },
+        };
+        toml::to_string(&config).context("cannot serialize config")
+    }
+
+    /// Load the config file and apply the command line overrides to every job.
+    fn load_config(&self, path: &Path) -> Result<Config> {
+        let mut config = Config::load(path)?;
+        // The options that only apply to cronthat itself are not serialized, so not overridden.
+        let options = toml::Table::try_from(self).context("cannot serialize options")?;
+        for (i, job) in config.jobs.iter_mut().enumerate() {
+            let mut merged =
+                toml::Table::try_from(&job.cronthat).context("cannot serialize config")?;
+            for name in &self.overrides {
+                if let Some(value) = options.get(name) {
+                    merged.insert(name.clone(), value.clone());
+                }
+            }
+            job.cronthat = merged
+                .try_into()
+                .with_context(|| format!("job #{} in {:?}", i + 1, path))?;
+            if let Err(err) = job.cronthat.check_job() {
+                bail!("job #{} {} in {:?}", i + 1, err, path);
+            }
+            if self.no_now {
+                job.cronthat.now = false;
+            }
+            if self.follow_symlinks {
+                job.cronthat.resolve_symlinks()?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// Replace every path argument with its canonical path for --follow-symlinks.
+    fn resolve_symlinks(&mut self) -> Result<()> {
+        let paths = [
+            &mut self.holidays,
+            &mut self.log_file,
+            &mut self.pid_file,
+            &mut self.control_fifo,
+            &mut self.state_file,
+            &mut self.last_run_file,
+            &mut self.failure_file,
+            &mut self.config,
+            &mut self.diff,
+            &mut self.lock_file,
+            &mut self.pid_lock,
+            &mut self.schedule_file,
+            &mut self.budget_file,
+            &mut self.change_state_file,
+            &mut self.stop_file,
+        ];
+        for path in paths.into_iter().flatten() {
+            *path = canonical_path(path)?;
+        }
+        for path in &mut self.tee {
+            *path = canonical_path(path)?;
+        }
+        for env_file in &mut self.env_from_file {
+            env_file.path = canonical_path(&env_file.path)?;
+        }
+        if let Some(marker) = &mut self.until_file_contains {
+            marker.set_path(canonical_path(&marker.path)?)?;
+        }
+        Ok(())
+    }
+
+    /// Print the problems of every job of the config file, the ones it would fail with at startup.
+    fn validate_config(&self, path: &Path) -> Result<()> {
+        let config = Config::load(path)?;
+        if config.jobs.is_empty() {
+            bail!("no job defined in {:?}", path);
+        }
+
+        let mut invalid_jobs = 0;
+        for (i, job) in config.jobs.iter().enumerate() {
+            let name = job
+                .name
+                .clone()
+                .unwrap_or_else(|| format!("job #{}", i + 1));
+            match job
+                .cronthat
+                .check_job()
+                .and_then(|_| job.cronthat.validate())
+            {
+                Ok(_) => println!("{}: ok", name),
+                Err(err) => {
+                    println!("{}: {:#}", name, err);
+                    invalid_jobs += 1;
+                }
+            }
+        }
+
+        if invalid_jobs > 0 {
+            bail!("{} job(s) invalid in {:?}", invalid_jobs, path);
+        }
+        Ok(())
+    }
+
+    /// Rules that only apply to the jobs of a config file, where the first positional argument
+    /// cannot be mistaken for the command.
+    fn check_job(&self) -> Result<()> {
+        if self.cron_expression.is_some() && self.schedule_file.is_some() {
+            bail!("sets both cron_expression and schedule_file");
+        }
+        if self.composes_schedule() {
+            if self.cron_expression.is_some() {
+                bail!("sets both cron_expression and at_time, days, months or weekdays");
+            }
+            if self.schedule_file.is_some() {
+                bail!("sets both schedule_file and at_time, days, months or weekdays");
+            }
+        }
+        Ok(())
+    }
+
+    /// Run every job of the config file in its own thread and print a combined summary once they
+    /// are all done. With --watch-config, the jobs are restarted whenever the file changes.
+    fn run_jobs(&self, path: &Path, shutdown: &Shutdown) -> Result<State> {
+        let mut config = self.load_config(path)?;
+        if config.jobs.is_empty() {
+            bail!("no job defined in {:?}", path);
+        }
+
+        let mut total = State::default();
+        let mut failed_jobs = 0;
+        loop {
+            let jobs_shutdown = Shutdown::default();
+            let jobs_done = Shutdown::default();
+            let (results, reloaded): (Vec<(String, Result<State>)>, _) = thread::scope(|scope| {
+                let watcher = self.watch_config.then(|| {
+                    scope.spawn(|| self.watch_config(path, shutdown, &jobs_shutdown, &jobs_done))
+                });
+                let job_shutdown = if self.watch_config {
+                    &jobs_shutdown
+                } else {
+                    shutdown
+                };
+                let handles: Vec<_> = config
+                    .jobs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, job)| {
+                        let name = job
+                            .name
+                            .clone()
+                            .unwrap_or_else(|| format!("job #{}", i + 1));
+                        (name, scope.spawn(|| job.cronthat.run(job_shutdown)))
+                    })
+                    .collect();
+                let results = handles
+                    .into_iter()
+                    .map(|(name, handle)| {
+                        let result = handle
+                            .join()
+                            .unwrap_or_else(|_| Err(anyhow!("job panicked")));
+                        (name, result)
+                    })
+                    .collect();
+                jobs_done.trigger();
+                let reloaded = watcher.and_then(|watcher| watcher.join().ok().flatten());
+                (results, reloaded)
+            });
+
+            info!("{} -- Summary", self.log_time());
+            for (name, result) in results {
+                match result {
+                    Ok(state) => {
+                        info!("{}: {} runs, {} failures", name, state.runs, state.failures);
+                        total.runs += state.runs;
+                        total.failures += state.failures;
+                        total.last_run = total.last_run.max(state.last_run);
+                    }
+                    Err(err) => {
+                        info!("{}: {:#}", name, err);
+                        failed_jobs += 1;
+                    }
+                }
+            }
+
+            match reloaded {
+                Some(reloaded) if !shutdown.is_triggered() => config = reloaded,
+                _ => break,
+            }
+        }
+
+        if failed_jobs > 0 {
+            bail!("{} job(s) failed", failed_jobs);
+        }
+        Ok(total)
+    }
+
+    /// Poll the config file until `done` is triggered and return its new content once it changed
+    /// and is valid, after triggering `jobs` to stop the running jobs. `shutdown` is forwarded to
+    /// `jobs`.
+    fn watch_config(
+        &self,
+        path: &Path,
+        shutdown: &Shutdown,
+        jobs: &Shutdown,
+        done: &Shutdown,
+    ) -> Option<Config> {
+        let modified = || {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        };
+        let mut last_modified = modified();
+        while !done.sleep(WATCH_CONFIG_INTERVAL) {
+            if shutdown.is_triggered() {
+                jobs.trigger();
+                return None;
+            }
+            let current = modified();
+            if current == last_modified {
+                continue;
+            }
+            last_modified = current;
+            match self.reload_config(path) {
+                Ok(config) => {
+                    info!(
+                        "{} -- Config {:?} changed, restarting the jobs after their current \
+                         execution",
+                        self.log_time(),
+                        path
+                    );
+                    jobs.trigger();
+                    return Some(config);
+                }
+                Err(err) => warning!(
+                    "warning: cannot reload config {:?} ({:#}), keeping the current jobs",
+                    path,
+                    err
+                ),
+            }
+        }
+        None
+    }
+
+    /// Load the changed config file for --watch-config, failing if any job is invalid. The new
+    /// jobs wait for their next scheduled execution even if they set `now`.
+    fn reload_config(&self, path: &Path) -> Result<Config> {
+        let mut config = self.load_config(path)?;
+        if config.jobs.is_empty() {
+            bail!("no job defined");
+        }
+        for (i, job) in config.jobs.iter_mut().enumerate() {
+            job.cronthat
+                .validate()
+                .with_context(|| format!("job #{}", i + 1))?;
+            job.cronthat.now = false;
+        }
+        Ok(config)
+    }
+
+    fn claim_slot(&self, datetime: &DateTime<Local>) -> Result<bool> {
+        match &self.lock_file {
+            Some(path) if self.concurrent_safe => {
+                lock::claim_slot(path, &self.command_lines().join("\n"), datetime)
+            }
+            _ => Ok(true),
+        }
+    }
+
+    fn record(&self, state: &mut State, context: &mut RunContext, succeeded: bool) -> Result<()> {
+        state.record(succeeded);
+        if succeeded {
+            if let Some(message) = &self.success_message {
+                info!("{}", expand_message(message));
+            }
+            let now = Local::now();
+            context.last_success = Some(now);
+            context.successes += 1;
+            if let Some(path) = &self.last_run_file {
+                if let Err(err) = fs::write(path, now.to_rfc3339()) {
+                    warning!("warning: cannot write last run file {:?}: {}", path, err);
+                }
+            }
+        }
+        if let Some(path) = &self.failure_file {
+            self.update_failure_file(path, state, context, succeeded);
+        }
+        if let Some(url) = &self.pushgateway {
+            let metrics = Metrics {
+                exit_code: context.last_exit_code.unwrap_or_default(),
+                duration: context.last_duration,
+                last_success: context.last_success,
+            };
+            let job = self.job_name.as_deref().unwrap_or("cronthat");
+            if let Err(err) = pushgateway::push(url, job, &self.tag, &metrics) {
+                warning!("warning: cannot push metrics to {}: {:#}", url, err);
+            }
+        }
+        if let Some(path) = &self.state_file {
+            state.save(path)?;
+        }
+        if let Some(path) = &self.budget_file {
+            budget::write_used(path, context.spent)?;
+        }
+        Ok(())
+    }
+
+    /// Write the details of a failed execution to --failure-file, or remove it after a successful
+    /// one. Errors are only logged.
+    fn update_failure_file(
+        &self,
+        path: &Path,
+        state: &State,
+        context: &RunContext,
+        succeeded: bool,
+    ) {
+        let result = if succeeded {
+            match fs::remove_file(path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err.into()),
+                _ => Ok(()),
+            }
+        } else {
+            let failure = Failure {
+                timestamp: Local::now(),
+                exit_code: context.last_exit_code.unwrap_or_default(),
+                command: &context.last_command,
+                run_number: state.runs,
+            };
+            serde_json::to_string(&failure)
+                .map_err(anyhow::Error::from)
+                .and_then(|json| write_atomically(path, json))
+        };
+        if let Err(err) = result {
+            warning!("warning: cannot update failure file {:?}: {:#}", path, err);
+        }
+    }
+
+    /// Ask whether to start running the command on `cron_expression`, --yes answers for the user.
+    fn confirmed(&self, cron_expression: &str) -> Result<bool> {
+        if self.yes {
+            return Ok(true);
+        }
+        if !io::stdin().is_terminal() {
+            bail!("--confirm needs an interactive terminal, add --yes to skip the prompt");
+        }
+        print!(
+            "Run '{}' on schedule '{}'? [y/N] ",
+            self.command_lines().join("', '"),
+            cron_expression
+        );
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim(), "y" | "Y" | "yes" | "YES"))
+    }
+
+    /// Start the status endpoint if --status-addr is set and return the buffer it serves.
+    fn serve_status(&self) -> Result<Option<LogBuffer>> {
+        let Some(address) = &self.status_addr else {
+            return Ok(None);
+        };
+        let listener =
+            TcpListener::bind(address).with_context(|| format!("cannot listen on {}", address))?;
+        info!(
+            "{} -- Serving status on http://{}",
+            self.log_time(),
+            listener.local_addr()?
+        );
+        let log_buffer = LogBuffer::new(self.log_buffer_lines);
+        status::serve(listener, log_buffer.clone());
+        Ok(Some(log_buffer))
+    }
+
+    /// Whether --run-at-startup-if-missed asks for an immediate execution.
+    fn missed_run(&self, state: &State) -> bool {
+        let Some(max_age) = self.run_at_startup_if_missed else {
+            return false;
+        };
+        match state.last_success {
+            Some(last_success) if Local::now() - last_success <= max_age => false,
+            Some(last_success) => {
+                info!(
+                    "{} -- Last success was {} ago, running now",
+                    self.log_time(),
+                    format_duration(Local::now() - last_success)
+                );
+                true
+            }
+            None => {
+                info!("{} -- No success recorded, running now", self.log_time());
+                true
+            }
+        }
+    }
+
+    /// Random duration between zero and `window`, drawn from --seed if set.
+    fn splay(&self, window: Duration) -> Duration {
+        let mut rng = match self.seed {
+            Some(seed) => fastrand::Rng::with_seed(seed),
+            None => fastrand::Rng::new(),
+        };
+        Duration::milliseconds(rng.i64(0..=window.num_milliseconds()))
+    }
+
+    /// Whether --run-on-start-if-overdue asks for an immediate execution.
+    fn overdue_run(&self, state: &State, schedule: &Schedule) -> bool {
+        if !self.run_on_start_if_overdue {
+            return false;
+        }
+        let Some(last_run) = state.last_run else {
+            info!("{} -- No run recorded, running now", self.log_time());
+            return true;
+        };
+        match self.next_fire_time(schedule, Some(&last_run)) {
+            Some(scheduled) if scheduled <= Local::now() => {
+                info!(
+                    "{} -- Execution scheduled at {} was missed, running now",
+                    self.log_time(),
+                    self.format_time(&scheduled)
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn stop_file_found(&self) -> Result<bool> {
+        match &self.stop_file {
+            Some(path) if path.exists() => {
+                info!(
+                    "{} -- Stop file {:?} found, stopping",
+                    self.log_time(),
+                    path
+                );
+                if self.remove_stop_file {
+                    fs::remove_file(path)
+                        .with_context(|| format!("cannot remove stop file {:?}", path))?;
+                }
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn marker_found(&self) -> Result<bool> {
+        match &self.until_file_contains {
+            Some(marker) if marker.found()? => {
+                info!(
+                    "{} -- File {:?} contains '{}', stopping",
+                    self.log_time(),
+                    marker.path,
+                    marker.pattern
+                );
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// Run --stop-if and --continue-if, returns whether one of them asks to stop.
+    fn stop_condition_met(&self) -> Result<bool> {
+        if let Some(stop_if) = &self.stop_if {
+            if self.run_guard(stop_if)? == 0 {
+                info!(
+                    "{} -- Stop condition '{}' met, stopping",
+                    self.log_time(),
+                    stop_if
+                );
+                return Ok(true);
+            }
+        }
+        if let Some(continue_if) = &self.continue_if {
+            let status = self.run_guard(continue_if)?;
+            if status != 0 {
+                info!(
+                    "{} -- Continue condition '{}' exited with status {}, stopping",
+                    self.log_time(),
+                    continue_if,
+                    status
+                );
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    /// Run every guard once and fail on the exit codes shells use for commands that cannot be
+    /// found (127) or executed (126), which usually means a typo.
+    fn validate_guards(&self) -> Result<()> {
+        for guard in self.stop_if.iter().chain(&self.continue_if) {
+            let status = self.run_guard(guard)?;
+            if status == 126 || status == 127 {
+                bail!("guard '{}' cannot be run (status {})", guard, status);
+            }
+        }
+        Ok(())
+    }
+
+    fn run_guard(&self, guard: &str) -> Result<i32> {
+        let (status, _) =
+            self.run_stages(&[guard.to_string()], &HashMap::new(), None, None, None)?;
+        Ok(status)
+    }
+
+    fn budget_exhausted(&self, context: &RunContext) -> bool {
+        match self.budget_max_ms {
+            Some(max) if context.spent.num_milliseconds() >= max as i64 => {
+                warning!(
+                    "warning: execution budget exhausted, {} spent out of {}",
+                    format_duration(context.spent),
+                    format_duration(Duration::milliseconds(max as i64))
+                );
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Run the command and retry it while it fails and the retry budget allows it, the global
+    /// budget is only consumed with `--retry-scope global`.
+    fn spawn_with_retries(
+        &self,
+        scheduled: Option<&DateTime<Local>>,
+        context: &mut RunContext,
+    ) -> Result<i32> {
+        context.trace_id = self.new_trace_id();
+        let mut tick_retries = self.retry;
+        let first_attempt = Local::now();
+        let mut status = self.spawn_command(scheduled, context)?;
+        while !self.succeeded(status) {
+            if !self.retry_codes.is_empty() && !self.retry_codes.contains(&status) {
+                warning!(
+                    "warning: exit code {} is not in --retry-codes, not retrying",
+                    status
+                );
+                break;
+            }
+            let elapsed = Local::now().signed_duration_since(first_attempt);
+            if let Some(max_elapsed) = self.retry_max_elapsed.filter(|max| elapsed >= *max) {
+                warning!(
+                    "warning: giving up retries after {} (--retry-max-elapsed is {})",
+                    format_duration(elapsed),
+                    format_duration(max_elapsed)
+                );
+                break;
+            }
+            if self.until_stops() && self.until.is_some_and(|until| Local::now() > until)
+                || context
+                    .total_deadline
+                    .is_some_and(|deadline| Local::now() > deadline)
+            {
+                break;
+            }
+            let retries = match self.retry_scope {
+                RetryScope::Tick => &mut tick_retries,
+                RetryScope::Global => &mut context.global_retries,
+            };
+            if *retries == 0 {
+                break;
+            }
+            *retries -= 1;
+            info!(
+                "{} -- Retrying failed execution, {} retries left",
+                self.log_time(),
+                retries
+            );
+            status = self.spawn_command(scheduled, context)?;
+        }
+        context.cycles += 1;
+        Ok(status)
+    }
+
+    /// Run the command once, record it in `context` and return its exit code, `scheduled` is the
+    /// time it was scheduled at if it was not an immediate execution.
+    fn spawn_command(
+        &self,
+        scheduled: Option<&DateTime<Local>>,
+        context: &mut RunContext,
+    ) -> Result<i32> {
+        let started = Local::now();
+        let late = scheduled.map(|scheduled| started.signed_duration_since(*scheduled));
+        let trace = match &context.trace_id {
+            Some(trace_id) => format!(" [trace {}]", trace_id),
+            None => String::new(),
+        };
+        match late {
+            Some(late) if self.since => {
+                info!(
+                    "{} -- Spawning command, {}{}",
+                    self.format_time(&started),
+                    started_late(late),
+                    trace
+                )
+            }
+            _ => info!(
+                "{} -- Spawning command{}",
+                self.format_time(&started),
+                trace
+            ),
+        }
+        if let (Some(late), Some(threshold)) = (late, self.late_threshold) {
+            if late > threshold {
+                warning!(
+                    "warning: execution {} (threshold is {})",
+                    started_late(late),
+                    format_duration(threshold)
+                );
+            }
+        }
+
+        let commands = self.command_lines();
+        let command = commands[context.cycles % commands.len()].clone();
+        if self.cycle {
+            info!(
+                "{} -- Running command {} of {}: {}",
+                self.log_time(),
+                context.cycles % commands.len() + 1,
+                commands.len(),
+                command
+            );
+        }
+        context.last_command.clone_from(&command);
+        if let Some(dashboard) = &context.dashboard {
+            dashboard.started(&command);
+        }
+        let mut env = self.command_env(context);
+        env.extend(secret::read_env_files(
+            &self.env_from_file,
+            self.optional_secrets,
+        )?);
+        let capture = self.capture_output();
+        let stdin = self
+            .stdin_template
+            .as_ref()
+            .map(|template| expand_template(template, scheduled.unwrap_or(&started)));
+        let start = Local::now();
+        let stages = if self.pipeline {
+            self.pipeline_stages()
+        } else {
+            vec![command]
+        };
+        let iteration = self.iteration(context).to_string();
+        let stages: Vec<String> = stages
+            .iter()
+            .map(|stage| stage.replace(ITERATION_PLACEHOLDER, &iteration))
+            .collect();
+        let stages = if self.expand_env {
+            stages
+                .iter()
+                .map(|stage| expand_env(stage, self.allow_unset_env))
+                .collect::<Result<_>>()?
+        } else {
+            stages
+        };
+        let mut stages: Vec<String> = match &self.wrap_in_timeout_cmd {
+            Some(wrapper) => stages
+                .into_iter()
+                .map(|stage| format!("{} {}", wrapper.trim(), stage))
+                .collect(),
+            None => stages,
+        };
+        self.check_command_length(&stages)?;
+        if let Some(destination) = &self.ssh {
+            let remote_env = self.command_env(context);
+            stages = stages
+                .iter()
+                .map(|stage| self.ssh_stage(destination, stage, &remote_env))
+                .collect();
+        }
+        stages.extend(self.pipe_to.clone());
+        let command_line = stages.join(" | ");
+        if self.echo_command {
+            info!("{} -- Running: {}", self.log_time(), command_line);
+        }
+        let (mut status, captured) = self.run_stages(
+            &stages,
+            &env,
+            stdin,
+            capture.then_some(&*context),
+            self.deadline(context)?
+                .map(|deadline| (deadline, &context.shutdown)),
+        )?;
+        let output = output::decode(&captured.stdout);
+        context.missing_program = None;
+        if status == COMMAND_NOT_FOUND_STATUS {
+            context.missing_program = stages.iter().find_map(|stage| missing_program(stage));
+            if let Some(program) = &context.missing_program {
+                error!("error: command '{}' not found in PATH", program);
+            }
+        }
+        if status == SSH_ERROR_STATUS {
+            if let Some(destination) = &self.ssh {
+                warning!(
+                    "warning: ssh exited with status 255, cannot reach {}?",
+                    destination
+                );
+            }
+        }
+        if status != 0 && self.command_timeout_exit_code == Some(status) {
+            warning!(
+                "warning: command timed out, exited with status {} (--command-timeout-exit-code)",
+                status
+            );
+            status = TIMEOUT_STATUS;
+        }
+        if let Some(log_buffer) = &context.log_buffer {
+            log_buffer.push_output(&output);
+        }
+        if self.command_output_to_env {
+            context.previous_output = Some(previous_output(&output));
+        }
+        if capture && self.succeeded(status) {
+            if let Err(err) = self.check_output(&output) {
+                warning!("warning: {:#}", err);
+                status = 1;
+            }
+        }
+        if self.hashes_output() {
+            self.record_output_hash(&captured.stdout, context);
+        }
+        if let Some(lines) = self.tail_on_failure.filter(|_| !self.succeeded(status)) {
+            info!(
+                "{} -- Last {} lines of the output of the failed execution:",
+                self.log_time(),
+                lines
+            );
+            print!("{}", output::tail(&output, lines));
+        }
+        let duration = Local::now().signed_duration_since(start);
+        context.last_exit_code = Some(status);
+        context.spent += duration;
+        context.last_duration = duration;
+        context.last_finished = Some(Local::now());
+        if let Some(dashboard) = &context.dashboard {
+            dashboard.finished(status, self.succeeded(status));
+        }
+        context.executions += 1;
+        let elapsed = format_duration(duration);
+        if self.report_usage {
+            info!(
+                "{} -- Command finished in {} (cpu {}, max rss {} KiB){}",
+                self.log_time(),
+                elapsed,
+                format_duration(captured.usage.cpu),
+                captured.usage.max_rss_kib,
+                trace
+            );
+        } else {
+            info!(
+                "{} -- Command finished in {}{}",
+                self.log_time(),
+                elapsed,
+                trace
+            );
+        }
+        if self.json_runs {
+            let mut record = RunRecord::new(status, duration, &captured.stdout, &captured.stderr);
+            if self.echo_command {
+                record.command = Some(command_line);
+            }
+            println!("{}", serde_json::to_string(&record)?);
+        }
+        if let Some(after_command) = &self.after_command {
+            self.run_after_command(after_command, status, context)?;
+        }
+        Ok(status)
+    }
+
+    /// Why the last execution failed, or --failure-message.
+    fn failure_reason(&self, context: &RunContext) -> String {
+        if let Some(message) = &self.failure_message {
+            return expand_message(message);
+        }
+        match context.last_exit_code {
+            Some(status) if !self.success_codes.is_empty() => format!(
+                "command exited with status code {}, not in --success-codes",
+                status
+            ),
+            _ => "command exited with non-zero status code".to_string(),
+        }
+    }
+
+    /// Whether `status` is one of --success-codes, 0 by default.
+    fn succeeded(&self, status: i32) -> bool {
+        if self.run_until_code == Some(status) {
+            true
+        } else if self.success_codes.is_empty() {
+            status == 0
+        } else {
+            self.success_codes.contains(&status)
+        }
+    }
+
+    /// Whether the command exited with --run-until-code, after `runs` runs.
+    fn run_until_code_reached(&self, status: i32, runs: usize) -> bool {
+        if self.run_until_code != Some(status) {
+            return false;
+        }
+        info!(
+            "{} -- Command exited with --run-until-code {} after {} runs, stopping",
+            self.log_time(),
+            status,
+            runs
+        );
+        true
+    }
+
+    /// Whether the output is only printed when it changed, with --hash-output or the options
+    /// implying it.
+    fn hashes_output(&self) -> bool {
+        self.hash_output || self.on_change.is_some() || self.change_state_file.is_some()
+    }
+
+    /// Remember the hash of `output`, keep it in --change-state-file and run --on-change if it
+    /// differs from the previous execution's.
+    fn record_output_hash(&self, output: &[u8], context: &mut RunContext) {
+        let hash = output_hash(output);
+        let previous = context.output_hash.replace(hash.clone());
+        if previous.as_ref() == Some(&hash) {
+            info!(
+                "{} -- Output unchanged since the previous execution",
+                self.log_time()
+            );
+            return;
+        }
+        if let Some(path) = &self.change_state_file {
+            if let Err(err) = write_atomically(path, &hash) {
+                warning!("warning: cannot write change state file: {:#}", err);
+            }
+        }
+        // The first output has nothing to be compared with.
+        let (Some(previous), Some(script)) = (previous, &self.on_change) else {
+            return;
+        };
+        info!("{} -- Output changed, running --on-change", self.log_time());
+        let env = HashMap::from([
+            ("CRONTHAT_OUTPUT_HASH".to_string(), hash),
+            ("CRONTHAT_PREVIOUS_OUTPUT_HASH".to_string(), previous),
+        ]);
+        match self.run_stages(&[script.to_string()], &env, None, None, None) {
+            Ok((0, _)) => {}
+            Ok((status, _)) => warning!("warning: on change command exited with status {}", status),
+            Err(err) => warning!("warning: cannot run on change command: {:#}", err),
+        }
+    }
+
+    /// Run --after-command and log its exit code without affecting the execution's result.
+    fn run_after_command(&self, script: &str, status: i32, context: &RunContext) -> Result<()> {
+        let env = HashMap::from([
+            ("CRONTHAT_EXIT_CODE".to_string(), status.to_string()),
+            (
+                "CRONTHAT_DURATION_MS".to_string(),
+                context.last_duration.num_milliseconds().to_string(),
+            ),
+            (
+                "CRONTHAT_RUN_NUMBER".to_string(),
+                context.executions.to_string(),
+            ),
+        ]);
+        let (after_status, _) = self.run_stages(&[script.to_string()], &env, None, None, None)?;
+        if after_status != 0 {
+            warning!("warning: after command exited with status {}", after_status);
+        }
+        Ok(())
+    }
+
+    /// When the next execution must be killed, the earliest of --timeout-per-run and
+    /// --timeout-total.
+    fn deadline(&self, context: &RunContext) -> Result<Option<DateTime<Local>>> {
+        let per_run = self
+            .timeout_per_run
+            .map(|timeout| deadline_after(timeout, "--timeout-per-run"))
+            .transpose()?;
+        Ok(per_run.into_iter().chain(context.total_deadline).min())
+    }
+
+    /// Whether the command's output must be captured rather than inherited.
+    fn capture_output(&self) -> bool {
+        self.json_command_output
+            || !self.expect_json_field.is_empty()
+            || !self.expect_output_regex.is_empty()
+            || !self.fail_output_regex.is_empty()
+            || self.max_output_lines.is_some()
+            || self.output_prefix_command.is_some()
+            || self.command_output_to_env
+            || self.tail_on_failure.is_some()
+            || self.status_addr.is_some()
+            || self.output_encoding.is_some()
+            || self.hashes_output()
+            || self.json_runs
+    }
+
+    /// Check the captured output of a successful execution against the output assertions.
+    fn check_output(&self, output: &str) -> Result<()> {
+        if self.json_command_output || !self.expect_json_field.is_empty() {
+            output::check_json(output, &self.expect_json_field)?;
+        }
+        output::check_regexes(output, &self.expect_output_regex, &self.fail_output_regex)
+    }
+
+    /// Trace ID of a new execution, taken from --trace-id-from if set there.
+    fn new_trace_id(&self) -> Option<String> {
+        if let Some(trace_id) = self
+            .trace_id_from
+            .as_ref()
+            .and_then(|name| std::env::var(name).ok())
+        {
+            return Some(trace_id);
+        }
+        (self.trace_id || self.trace_id_from.is_some()).then(|| Uuid::new_v4().to_string())
+    }
+
+    /// Number of the current execution, retries excluded, starting at --count-from.
+    fn iteration(&self, context: &RunContext) -> usize {
+        self.count_from + context.cycles
+    }
+
+    /// Environment variables set for the command on top of cronthat's own environment.
+    fn command_env(&self, context: &RunContext) -> HashMap<String, String> {
+        let mut env = HashMap::from([(
+            "CRONTHAT_ITERATION".to_string(),
+            self.iteration(context).to_string(),
+        )]);
+        if let Some(code) = context.last_exit_code {
+            env.insert("CRONTHAT_LAST_EXIT_CODE".to_string(), code.to_string());
+        }
+        if let Some(trace_id) = &context.trace_id {
+            env.insert("CRONTHAT_TRACE_ID".to_string(), trace_id.clone());
+        }
+        if let Some(output) = &context.previous_output {
+            env.insert("CRONTHAT_PREV_OUTPUT".to_string(), output.clone());
+        }
+        if self.batch_window.is_some() {
+            env.insert(
+                "CRONTHAT_COALESCED_TICKS".to_string(),
+                context.coalesced_ticks.to_string(),
+            );
+        }
+        env
+    }
+
+    /// `stage` run on `destination` through ssh with `env` exported there.
+    fn ssh_stage(&self, destination: &str, stage: &str, env: &HashMap<String, String>) -> String {
+        let mut env: Vec<_> = env.iter().collect();
+        env.sort();
+        let mut remote = vec!["env".to_string()];
+        remote.extend(
+            env.into_iter()
+                .map(|(name, value)| format!("{}={}", name, shell_quote(value))),
+        );
+        remote.extend(["sh -c".to_string(), shell_quote(stage)]);
+
+        let mut ssh = vec!["ssh"];
+        ssh.extend(self.ssh_opts.as_deref().map(str::trim));
+        let destination = shell_quote(destination);
+        let remote = shell_quote(&remote.join(" "));
+        ssh.extend([destination.as_str(), "--", remote.as_str()]);
+        ssh.join(" ")
+    }
+
+    /// Spawn every stage of the pipeline (a single stage without --pipeline) with its stdin wired
+    /// to the previous stage's stdout, the first one reading `stdin` if given, and return the
+    /// rightmost non-zero exit code along with the last stage's output if captured, i.e. with the
+    /// `capture` context whose shutdown forwards the output held back as soon as it is triggered.
+    /// Stages still running at `deadline` are killed and the exit code is then `TIMEOUT_STATUS`.
+    /// They run in their own process group until then, so they are interrupted with SIGINT when
+    /// the shutdown given with the deadline is triggered, as CTRL-C would have done.
+    fn run_stages(
+        &self,
+        stages: &[String],
+        env: &HashMap<String, String>,
+        mut stdin: Option<String>,
+        capture: Option<&RunContext>,
+        deadline: Option<(DateTime<Local>, &Shutdown)>,
+    ) -> Result<(i32, Captured)> {
+        let mut children = Vec::with_capacity(stages.len());
+        let mut previous_stdout: Option<ChildStdout> = None;
+        let mut stdin_writer = None;
+        let mut stderr_reader = None;
+        for (i, stage) in stages.iter().enumerate() {
+            let mut command = shell_command(stage);
+            if self.clean_env || !self.pass_env_prefix.is_empty() {
+                command.env_clear();
+                command.envs(std::env::vars().filter(|(name, _)| {
+                    self.pass_env_prefix
+                        .iter()
+                        .any(|prefix| name.starts_with(prefix))
+                }));
+            }
+            command.envs(env);
+            if let Some(stdout) = previous_stdout.take() {
+                command.stdin(Stdio::from(stdout));
+            } else if stdin.is_some() {
+                command.stdin(Stdio::piped());
+            }
+            if capture.is_some() || i + 1 < stages.len() {
+                command.stdout(Stdio::piped());
+            }
+            let last = i + 1 == stages.len();
+            if capture.is_some() && last && self.json_runs {
+                command.stderr(Stdio::piped());
+            }
+            // In its own process group so that a timeout kills the processes it started too.
+            #[cfg(unix)]
+            if deadline.is_some() {
+                std::os::unix::process::CommandExt::process_group(&mut command, 0);
+            }
+            let mut child = command
+                .spawn()
+                .with_context(|| format!("cannot spawn '{}'", stage))?;
+            previous_stdout = child.stdout.take();
+            if let Some(mut child_stderr) = child.stderr.take() {
+                stderr_reader = Some(thread::spawn(move || {
+                    let mut stderr = Vec::new();
+                    child_stderr.read_to_end(&mut stderr).map(|_| stderr)
+                }));
+            }
+            // Written from another thread so that a command that doesn't read its whole input
+            // before writing its output cannot deadlock with us.
+            if let (Some(input), Some(mut child_stdin)) = (stdin.take(), child.stdin.take()) {
+                stdin_writer = Some(thread::spawn(move || {
+                    child_stdin.write_all(input.as_bytes())
+                }));
+            }
+            children.push((stage, child));
+        }
+
+        let watchdog = deadline.map(|(deadline, shutdown)| {
+            let pids: Vec<u32> = children.iter().map(|(_, child)| child.id()).collect();
+            let (done, finished) = mpsc::channel::<()>();
+            let shutdown = shutdown.clone();
+            let handle = thread::spawn(move || {
+                let mut interrupted = false;
+                loop {
+                    let left = (deadline - Local::now()).to_std().unwrap_or_default();
+                    match finished.recv_timeout(left.min(WATCHDOG_INTERVAL)) {
+                        Err(RecvTimeoutError::Timeout) if left <= WATCHDOG_INTERVAL => {
+                            pids.into_iter().for_each(kill_process_group);
+                            return true;
+                        }
+                        Err(RecvTimeoutError::Timeout) => {}
+                        _ => return false,
+                    }
+                    // Still killed at the deadline if they ignore it.
+                    if !interrupted && shutdown.is_triggered() {
+                        pids.iter().copied().for_each(interrupt_process_group);
+                        interrupted = true;
+                    }
+                }
+            });
+            (done, handle)
+        });
+
+        let mut captured = Captured::default();
+        if let (Some(stdout), Some(context)) = (previous_stdout, capture) {
+            // Printed once the execution is done with --tail-on-failure, and only if it changed
+            // with --hash-output. Only embedded in the JSON lines with --json-runs.
+            let mut held = Vec::new();
+            let writer: Box<dyn Write> = if self.tail_on_failure.is_some() || self.json_runs {
+                Box::new(io::sink())
+            } else if self.hashes_output() {
+                Box::new(&mut held)
+            } else {
+                Box::new(io::stdout())
+            };
+            let stdout: Box<dyn Read + Send> = match self.output_encoding {
+                Some(encoding) => Box::new(Transcode::new(stdout, encoding)),
+                None => Box::new(stdout),
+            };
+            captured.stdout = output::forward(
+                stdout,
+                LineLimit::new(
+                    LinePrefix::new(writer, context.output_prefix.clone()),
+                    self.max_output_lines,
+                ),
+                self.flush_mode,
+                &context.shutdown,
+            )?;
+            if context.output_hash.as_ref() != Some(&output_hash(&captured.stdout)) {
+                io::stdout().write_all(&held)?;
+            }
+        }
+        if let Some(Ok(stderr)) = stderr_reader.map(|reader| reader.join()) {
+            captured.stderr = stderr.context("cannot read the command's stderr")?;
+        }
+
+        let mut status = 0;
+        for (stage, mut child) in children {
+            let (exit_status, usage) = usage::wait(&mut child)?;
+            captured.usage = captured.usage.add(usage);
+            let code = exit_status.code().unwrap_or(1);
+            if code != 0 {
+                if stages.len() > 1 {
+                    warning!(
+                        "warning: pipeline stage '{}' exited with status {}",
+                        stage,
+                        code
+                    );
+                }
+                status = code;
+            }
+        }
+        if let Some((done, handle)) = watchdog {
+            drop(done);
+            if handle.join().unwrap_or(false) {
+                warning!("warning: command killed, timeout reached");
+                status = TIMEOUT_STATUS;
+            }
+        }
+        if let Some(Ok(Err(err))) = stdin_writer.map(|writer| writer.join()) {
+            if err.kind() != io::ErrorKind::BrokenPipe {
+                warning!("warning: cannot write the command's stdin: {}", err);
+            }
+        }
+        Ok((status, captured))
+    }
+
+    /// Commands run by the executions, the ones given to --command with --cycle.
+    fn command_lines(&self) -> Vec<String> {
+        if self.cycle {
+            self.commands.clone()
+        } else {
+            vec![self.command.join(" ")]
+        }
+    }
+
+    fn pipeline_stages(&self) -> Vec<String> {
+        self.command
+            .split(|arg| arg == PIPELINE_SEPARATOR)
+            .map(|stage| stage.join(" "))
+            .collect()
+    }
+
+    /// Check the arguments and the schedule before running, return the cron expression and its
+    /// schedule.
+    fn validate(&self) -> Result<(String, Schedule)> {
+        self.check_args()?;
+        let cron_expression = self.read_cron_expression()?;
+        let schedule = schedule::parse(&cron_expression)?;
+        self.check_min_interval(&schedule)?;
+        Ok((cron_expression, schedule))
+    }
+
+    fn check_args(&self) -> Result<()> {
+        if self.cron_expression.is_none()
+            && self.schedule_file.is_none()
+            && !self.composes_schedule()
+        {
+            bail!("no cron expression");
+        }
+
+        if self.command.is_empty() && self.commands.is_empty() {
+            bail!("no command to execute");
+        }
+
+        if let Some(path) = self.lock_file.as_ref().filter(|_| self.concurrent_safe) {
+            if !path.to_string_lossy().contains(lock::HASH_PLACEHOLDER) {
+                bail!(
+                    "--lock-file {:?} has no \"{}\", with --concurrent-safe every execution \
+                     would claim the same slot",
+                    path,
+                    lock::HASH_PLACEHOLDER
+                );
+            }
+        }
+
+        if self
+            .wrap_in_timeout_cmd
+            .as_ref()
+            .is_some_and(|wrapper| wrapper.trim().is_empty())
+        {
+            bail!("empty --wrap-in-timeout-cmd");
+        }
+
+        for (duration, option) in [
+            (self.timeout_per_run, "--timeout-per-run"),
+            (self.timeout_total, "--timeout-total"),
+            (self.batch_window, "--batch-window"),
+            (self.dedup_window, "--dedup-window"),
+            (self.late_threshold, "--late-threshold"),
+            (self.min_interval, "--min-interval"),
+            (self.run_at_startup_if_missed, "--run-at-startup-if-missed"),
+            (self.startup_delay, "--startup-delay"),
+            (self.startup_splay, "--startup-splay"),
+            (self.retry_max_elapsed, "--retry-max-elapsed"),
+        ] {
+            if let Some(duration) = duration.filter(|duration| *duration < Duration::zero()) {
+                bail!("negative {} {}", option, format_duration(duration));
+            }
+        }
+        for (timeout, option) in [
+            (self.timeout_per_run, "--timeout-per-run"),
+            (self.timeout_total, "--timeout-total"),
+        ] {
+            if let Some(timeout) = timeout {
+                deadline_after(timeout, option)?;
+            }
+        }
+
+        if self
+            .pipe_to
+            .as_ref()
+            .is_some_and(|pipe_to| pipe_to.trim().is_empty())
+        {
+            bail!("empty --pipe-to command");
+        }
+
+        if self.command_timeout_exit_code == Some(0) {
+            bail!("--command-timeout-exit-code cannot be 0, it is the success exit code");
+        }
+
+        if self.every_nth == Some(0) {
+            bail!("--every-nth must be at least 1");
+        }
+
+        if self.max_consecutive_skips == Some(0) {
+            bail!("--max-consecutive-skips must be at least 1");
+        }
+
+        if self.daemon && !cfg!(unix) {
+            bail!("--daemon is only supported on Unix");
+        }
+
+        if self.run_now_on_signal && !cfg!(unix) {
+            bail!("--run-now-on-signal is only supported on Unix");
+        }
+
+        if self.control_fifo.is_some() && !cfg!(unix) {
+            bail!("--control-fifo is only supported on Unix");
+        }
+
+        if self.report_usage && !cfg!(unix) {
+            bail!("--report-usage is only supported on Unix");
+        }
+
+        if self.pipeline && self.pipeline_stages().iter().any(|stage| stage.is_empty()) {
+            bail!("empty stage in --pipeline command");
+        }
+
+        if self.pipeline {
+            self.check_command_length(&self.pipeline_stages())?;
+        }
+        for command in self.command_lines() {
+            if !self.pipeline {
+                self.check_command_length(std::slice::from_ref(&command))?;
+            }
+            if self.expand_env {
+                expand_env(&command, self.allow_unset_env)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fail if two of the next executions of the schedule are closer than --min-interval.
+    fn check_min_interval(&self, schedule: &Schedule) -> Result<()> {
+        let Some(min_interval) = self.min_interval else {
+            return Ok(());
+        };
+        if let Some(interval) = self.shortest_interval(schedule, Local::now()) {
+            if interval < min_interval {
+                bail!(
+                    "schedule runs every {}, more often than --min-interval {}",
+                    format_duration(interval),
+                    format_duration(min_interval)
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Smallest time between two of the next executions of `schedule` after `from`, looked up in
+    /// --cron-timezone like the scheduler does.
+    fn shortest_interval(&self, schedule: &Schedule, from: DateTime<Local>) -> Option<Duration> {
+        let upcoming: Vec<_> = iter::successors(
+            schedule::next_after(schedule, &from, self.cron_timezone),
+            |previous| schedule::next_after(schedule, previous, self.cron_timezone),
+        )
+        .take(MIN_INTERVAL_SAMPLES)
+        .collect();
+        upcoming.windows(2).map(|pair| pair[1] - pair[0]).min()
+    }
+
+    /// Check every stage of a command against --max-command-length, naming the offending one
+    /// if it is part of a pipeline.
+    fn check_command_length(&self, stages: &[String]) -> Result<()> {
+        if self.max_command_length == 0 {
+            return Ok(());
+        }
+        let Some(stage) = stages
+            .iter()
+            .find(|stage| stage.len() > self.max_command_length)
+        else {
+            return Ok(());
+        };
+        if stages.len() > 1 {
+            bail!(
+                "pipeline stage '{}' is {} bytes long, more than --max-command-length {}",
+                stage,
+                stage.len(),
+                self.max_command_length
+            );
+        }
+        bail!(
+            "command is {} bytes long, more than --max-command-length {}",
+            stage.len(),
+            self.max_command_length
+        )
+    }
+
+    fn format_time(&self, datetime: &DateTime<Local>) -> String {
+        if let Some(format) = &self.timestamp_format {
+            format.format(datetime)
+        } else if self.iso_dates {
+            TimestampFormat::Rfc3339.format(datetime)
+        } else {
+            datetime.to_string()
+        }
+    }
+
+    /// Format of the timestamps set by --timestamp-format or --iso-dates, if any.
+    fn time_format(&self) -> Option<TimestampFormat> {
+        self.timestamp_format
+            .clone()
+            .or(self.iso_dates.then_some(TimestampFormat::Rfc3339))
+    }
+
+    /// Current time as printed at the start of log lines.
+    fn log_time(&self) -> String {
+        self.format_time(&Local::now())
+    }
+
+    /// One line explaining the wait before the `run`-th execution, e.g.
+    /// `Sleeping 4m12s until 2025-01-02 15:04:00 +01:00 (run 3 of 10)`.
+    fn explain_wait(&self, wait: Duration, datetime: &DateTime<Local>, run: usize) -> String {
+        let run = match (self.repetitions, self.until) {
+            (Some(repetitions), Some(until)) => format!(
+                "run {} of {} {} until {}",
+                run,
+                repetitions,
+                match self.stop_when {
+                    StopWhen::Any => "or",
+                    StopWhen::All => "and",
+                },
+                self.format_time(&until)
+            ),
+            (Some(repetitions), None) => format!("run {} of {}", run, repetitions),
+            (None, Some(until)) => format!("run {}, until {}", run, self.format_time(&until)),
+            (None, None) => format!("run {}", run),
+        };
+        format!(
+            "Sleeping {} until {} ({})",
+            format_duration(wait),
+            self.format_time(datetime),
+            run
+        )
+    }
+
+    fn must_stop(&self, i: usize) -> bool {
+        self.stop_reached(i, Local::now())
+    }
+
+    /// Whether --repetitions and --until, combined by --stop-when, are reached after `runs`
+    /// executions at `now`.
+    fn stop_reached(&self, runs: usize, now: DateTime<Local>) -> bool {
+        let mut reached = [
+            self.repetitions.map(|repetitions| runs >= repetitions),
+            self.until.map(|until| now > until),
+        ]
+        .into_iter()
+        .flatten()
+        .peekable();
+        match self.stop_when {
+            StopWhen::Any => reached.any(|reached| reached),
+            StopWhen::All => reached.peek().is_some() && reached.all(|reached| reached),
+        }
+    }
+
+    /// Whether reaching --until stops the run whatever the number of executions.
+    fn until_stops(&self) -> bool {
+        self.stop_when == StopWhen::Any || self.repetitions.is_none()
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::redundant_static_lifetimes, clippy::clone_on_copy)]
+mod tests {
+    use crate::cronthat::{
+        expand_env, expand_template, missing_program, output_hash, output_prefix, previous_output,
+        started_late, CronThat, DATETIME_FORMAT, PREV_OUTPUT_MAX_BYTES,
+    };
+    use crate::lock::LockFile;
+    use crate::shutdown::Shutdown;
+    use crate::state::State;
+    use chrono::{DateTime, Local, TimeDelta, TimeZone, Timelike};
+    use clap::Parser;
+    use regex::Regex;
+    use std::collections::HashMap;
+    use std::fs::File;
+    use std::io;
+    use std::io::Write;
+    use std::ops::Add;
+    use std::path::Path;
+    use std::str::FromStr;
+    use tokio::task::spawn_blocking;
+    use tokio::time::timeout;
+
+    static CRON_EVERY_S: &'static str = "* * * * * *";
+
+    /// Command creating a new file in `dir` each time it runs, see `run_times`.
+    fn timed_command(dir: &Path) -> String {
+        format!("mktemp {:?}/run.XXXXXX > /dev/null", dir)
+    }
+
+    /// When the runs of `timed_command` happened, in milliseconds since the epoch and in order.
+    /// Read from the files' modification times since `date` cannot print milliseconds portably,
+    /// they may be a few milliseconds early as file systems use a coarse clock.
+    fn run_times(dir: &Path) -> Vec<i64> {
+        let mut runs: Vec<i64> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| {
+                let modified = entry.unwrap().metadata().unwrap().modified().unwrap();
+                DateTime::<Local>::from(modified).timestamp_millis()
+            })
+            .collect();
+        runs.sort();
+        runs
+    }
+
+    #[test]
+    fn cronthat_parse_command() {
+        let cli =
+            CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--", "echo", "hello-world"])
+                .unwrap();
+        assert_eq!(cli.command, vec!["echo", "hello-world"]);
+    }
+
+    #[test]
+    fn cronthat_dry_run_diff() {
+        let dir = tempfile::tempdir().unwrap();
+        let plan_path = dir.path().join("plan.txt");
+        let dry_run = |cron_expression: &str| {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                cron_expression,
+                "--dry-run",
+                "--diff",
+                plan_path.to_str().unwrap(),
+                "--repetitions",
+                "4",
+                "--",
+                "echo",
+            ])
+            .unwrap();
+            cli.run(&Shutdown::default()).unwrap();
+            std::fs::read_to_string(&plan_path).unwrap()
+        };
+
+        let plan = dry_run("0 0 9 * * *");
+        assert_eq!(plan.lines().count(), 4);
+        assert!(plan
+            .lines()
+            .all(|line| line.parse::<DateTime<Local>>().unwrap().hour() == 9));
+        let plan = dry_run("0 0 9,21 * * *");
+        assert_eq!(plan.lines().count(), 4);
+        assert!(plan.lines().any(|line| line.contains("T21:00:00")));
+    }
+
+    #[test]
+    fn cronthat_expand_env() {
+        let path = std::env::var("PATH").unwrap();
+        assert_eq!(
+            expand_env("echo $PATH ${PATH}_suffix $1 $", false).unwrap(),
+            format!("echo {} {}_suffix $1 $", path, path)
+        );
+
+        let err = expand_env("echo ${CRONTHAT_UNSET_VARIABLE}", false).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("environment variable CRONTHAT_UNSET_VARIABLE is not set"));
+        assert_eq!(
+            expand_env("echo $CRONTHAT_UNSET_VARIABLE!", true).unwrap(),
+            "echo !"
+        );
+
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--expand-env",
+            "--",
+            "echo",
+            "$CRONTHAT_UNSET_VARIABLE",
+        ])
+        .unwrap();
+        cli.check_args().expect_err("unset variable");
+    }
+
+    #[test]
+    fn cronthat_max_command_length() {
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--max-command-length",
+            "16",
+            "--",
+            "echo",
+            "hello-world",
+        ])
+        .unwrap();
+        cli.check_args().unwrap();
+
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--max-command-length",
+            "16",
+            "--",
+            "echo",
+            "hello-world-and-beyond",
+        ])
+        .unwrap();
+        cli.check_args().expect_err("command too long");
+
+        // Each pipeline stage is checked on its own, without the separators.
+        let pipeline = |last: &'static str| {
+            CronThat::try_parse_from(vec![
+                "cronthat",
+                CRON_EVERY_S,
+                "--pipeline",
+                "--max-command-length",
+                "16",
+                "--",
+                "echo",
+                "hello-world",
+                ":::",
+                "tr",
+                last,
+                "A-Z",
+            ])
+            .unwrap()
+        };
+        pipeline("a-z").check_args().unwrap();
+        assert_eq!(
+            pipeline("abcdefghijklmn")
+                .check_args()
+                .unwrap_err()
+                .to_string(),
+            "pipeline stage 'tr abcdefghijklmn A-Z' is 21 bytes long, more than \
+             --max-command-length 16"
+        );
+
+        // Checked again once {iteration} is substituted and the wrapper added, see
+        // tests/max_command_length.rs for the variables.
+        for args in [
+            vec!["--wrap-in-timeout-cmd", "timeout 10", "--", "echo hello"],
+            vec!["--count-from", "123456789012345", "--", "echo {iteration}"],
+        ] {
+            let cli = CronThat::try_parse_from(
+                [
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--max-command-length",
+                    "16",
+                ]
+                .into_iter()
+                .chain(args),
+            )
+            .unwrap();
+            cli.check_args().unwrap();
+            let err = cli.run(&Shutdown::default()).err().unwrap();
+            assert!(
+                err.to_string()
+                    .ends_with("more than --max-command-length 16"),
+                "{}",
+                err
+            );
+        }
+    }
+
+    #[test]
+    fn cronthat_started_late() {
+        let scheduled = Local::now();
+        let started = scheduled.add(TimeDelta::milliseconds(1_200));
+        assert_eq!(
+            started_late(started.signed_duration_since(scheduled)),
+            "started 1.2s late"
+        );
+        assert_eq!(started_late(TimeDelta::milliseconds(-3)), "started 0s late");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_limited_repetitions() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let tmp_path = tmp_path.clone();
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\nhelloworld\n");
+    }
+
+    #[test]
+    fn stop_when() {
+        let now = Local::now();
+        let parse = |stop_when: &str, until: DateTime<Local>| {
+            CronThat::try_parse_from(vec![
+                "cronthat",
+                CRON_EVERY_S,
+                "--repetitions",
+                "2",
+                "--until",
+                &until.format(DATETIME_FORMAT).to_string(),
+                "--stop-when",
+                stop_when,
+                "true",
+            ])
+            .unwrap()
+        };
+        let future = now + TimeDelta::hours(1);
+        let past = now - TimeDelta::hours(1);
+
+        let any = parse("any", future);
+        assert!(!any.stop_reached(1, now));
+        assert!(any.stop_reached(2, now));
+        assert!(parse("any", past).stop_reached(1, now));
+
+        let all = parse("all", future);
+        assert!(!all.stop_reached(1, now));
+        assert!(!all.stop_reached(2, now));
+        assert!(all.stop_reached(2, future + TimeDelta::seconds(1)));
+        let all = parse("all", past);
+        assert!(!all.stop_reached(1, now));
+        assert!(all.stop_reached(2, now));
+
+        let cli =
+            CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--stop-when", "all", "true"])
+                .unwrap();
+        assert!(!cli.stop_reached(100, now));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_until() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let now = Local::now();
+        let in_three_seconds = now.add(TimeDelta::seconds(3));
+        let until = in_three_seconds.format(DATETIME_FORMAT).to_string();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let tmp_path = tmp_path.clone();
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--until",
+                    until.as_str(),
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\nhelloworld\nhelloworld\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_error() {
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+
+        // Default to ignore errors
+        timeout(timeout_duration.clone(), async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--",
+                    "exit",
+                    "1",
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        // Stop on errors
+        timeout(timeout_duration.clone(), async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--stop-on-error",
+                    "--",
+                    "exit",
+                    "1",
+                ])
+                .unwrap();
+                cli.execute().expect_err("must stop on error");
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[test]
+    fn cronthat_now_stop_on_error() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let run = |message: Option<&str>| {
+            let command = format!("echo run >> {:?}; exit 2", tmp.path());
+            let mut args = vec![
+                "cronthat",
+                CRON_EVERY_S,
+                "--now",
+                "-n",
+                "3",
+                "--stop-on-error",
+            ];
+            if let Some(message) = message {
+                args.extend(["--failure-message", message]);
+            }
+            args.extend(["--", &command]);
+            CronThat::try_parse_from(args)
+                .unwrap()
+                .run(&Shutdown::default())
+                .unwrap_err()
+                .to_string()
+        };
+
+        assert_eq!(run(None), "command exited with non-zero status code");
+        assert_eq!(std::fs::read_to_string(tmp.path()).unwrap(), "run\n");
+        assert_eq!(run(Some("backup failed")), "backup failed");
+        assert_eq!(std::fs::read_to_string(tmp.path()).unwrap(), "run\nrun\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_now() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_millis(1500);
+        let in_one_second = Local::now().add(TimeDelta::seconds(1));
+
+        // Default to ignore errors
+        timeout(timeout_duration.clone(), async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--until",
+                    &in_one_second.format(DATETIME_FORMAT).to_string(),
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\nhelloworld\n");
+    }
+
+    #[test]
+    fn fail_if_no_executions() {
+        let run = |command: &str| {
+            let in_two_seconds = Local::now().add(TimeDelta::seconds(2));
+            CronThat::try_parse_from(vec![
+                "cronthat",
+                CRON_EVERY_S,
+                "--until",
+                &in_two_seconds.format(DATETIME_FORMAT).to_string(),
+                "--fail-if-no-executions",
+                "--",
+                command,
+            ])
+            .unwrap()
+            .run(&Shutdown::default())
+        };
+
+        let err = run("exit 3").expect_err("no execution succeeded");
+        assert_eq!(err.to_string(), "no successful executions before deadline");
+        assert!(run("true").is_ok());
+
+        assert!(CronThat::try_parse_from([
+            "cronthat",
+            "--fail-if-no-executions",
+            CRON_EVERY_S,
+            "true"
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn run_until_code() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--now",
+            "--run-until-code",
+            "7",
+            "--",
+            &format!(
+                "echo run >> {:?}; [ $(wc -l < {:?}) -ge 3 ] && exit 7 || exit 1",
+                tmp.path(),
+                tmp.path()
+            ),
+        ])
+        .unwrap();
+        let state = cli.run(&Shutdown::default()).unwrap();
+        assert_eq!(state.runs, 3);
+        assert_eq!(state.failures, 2);
+        assert_eq!(state.consecutive_failures, 0);
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_now_counts_toward_repetitions() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "3",
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\nhelloworld\nhelloworld\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_now_then_aligned() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = timed_command(dir.path());
+
+        // Start in the middle of a second so that an unaligned cadence would show.
+        let subsec = Local::now().timestamp_subsec_millis();
+        std::thread::sleep(std::time::Duration::from_millis(
+            ((1_500 - subsec) % 1_000).into(),
+        ));
+        let start = Local::now();
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "3",
+                    "--",
+                    &command,
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        // Started half a second before the first tick, anything later is not immediate and an
+        // unaligned cadence would run half a second after the ticks.
+        let runs = run_times(dir.path());
+        assert_eq!(runs.len(), 3);
+        assert!(runs[0] - start.timestamp_millis() < 450, "immediate run");
+        for run in &runs[1..] {
+            assert!(
+                (run + 50) % 1_000 < 500,
+                "run at {} not aligned on a second",
+                run
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_command_not_found() {
+        assert_eq!(missing_program("FOO=bar sh -c true"), None);
+        assert_eq!(
+            missing_program("cronthat-no-such-command --flag"),
+            Some("cronthat-no-such-command".to_string())
+        );
+        assert_eq!(
+            missing_program("./no/such/script.sh"),
+            Some("./no/such/script.sh".to_string())
+        );
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        let err = timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--stop-on-error",
+                    "--",
+                    "cronthat-no-such-command",
+                ])
+                .unwrap();
+                cli.execute().unwrap_err()
+            })
+            .await
+            .unwrap()
+        })
+        .await
+        .expect("timed out");
+        assert!(err
+            .to_string()
+            .contains("command 'cronthat-no-such-command' not found in PATH"));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_pipeline() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "1",
+                    "--pipeline",
+                    "--",
+                    "echo",
+                    "helloworld",
+                    ":::",
+                    "tr",
+                    "a-z",
+                    "A-Z",
+                    ":::",
+                    &format!("cat >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "HELLOWORLD\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_pipeline_error() {
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--stop-on-error",
+                    "--pipeline",
+                    "--",
+                    "echo",
+                    "helloworld",
+                    ":::",
+                    "false",
+                    ":::",
+                    "cat",
+                ])
+                .unwrap();
+                cli.execute().expect_err("must stop on error");
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_assert_no_overlap() {
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--assert-no-overlap",
+                    "--",
+                    "sleep 1.3",
+                ])
+                .unwrap();
+                let err = cli.run(&Shutdown::default()).err().unwrap();
+                let time = r"\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2} [+-]\d{2}:\d{2}";
+                let message = Regex::new(&format!(
+                    "^execution scheduled at {time} was still running when the next one was \
+                     scheduled at {time}, overran by \\S+ \\(--assert-no-overlap\\)$"
+                ))
+                .unwrap();
+                assert!(message.is_match(&err.to_string()), "{}", err);
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_last_run_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let last_run_path = dir.path().join("last-run");
+        let start = Local::now();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let dir_path = dir.path().to_path_buf();
+            spawn_blocking(move || {
+                let run = |last_run_file: &Path, command: &str| {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--now",
+                        "--repetitions",
+                        "1",
+                        "--last-run-file",
+                        last_run_file.to_str().unwrap(),
+                        "--",
+                        command,
+                    ])
+                    .unwrap();
+                    cli.execute().unwrap();
+                };
+                run(&dir_path.join("last-run"), "true");
+                run(&dir_path.join("failed"), "false");
+                // Failing to write it doesn't stop the schedule.
+                run(&dir_path.join("missing").join("last-run"), "true");
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(last_run_path).unwrap()).unwrap();
+        let last_run = DateTime::parse_from_rfc3339(&content).unwrap();
+        assert!(last_run >= start - TimeDelta::seconds(1));
+        assert!(!dir.path().join("failed").exists());
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_command_output_to_env() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "3",
+                    "--command-output-to-env",
+                    "--",
+                    &format!(
+                        "echo ${{CRONTHAT_PREV_OUTPUT:-unset}} >> {:?}; echo \"  cursor-$CRONTHAT_ITERATION \"",
+                        tmp_path
+                    ),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "unset\ncursor-0\ncursor-1\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_on_change() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let dir = tempfile::tempdir().unwrap();
+        let change_state_file = dir.path().join("hash");
+
+        let timeout_duration = tokio::time::Duration::from_secs(8);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            let change_state_file = change_state_file.clone();
+            spawn_blocking(move || {
+                let run = |repetitions: &str, count_from: &str| {
+                    CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--now",
+                        "--repetitions",
+                        repetitions,
+                        "--count-from",
+                        count_from,
+                        "--on-change",
+                        &format!("echo changed >> {:?}", tmp_path),
+                        "--change-state-file",
+                        change_state_file.to_str().unwrap(),
+                        "--",
+                        "echo $((CRONTHAT_ITERATION / 2))",
+                    ])
+                    .unwrap()
+                    .execute()
+                    .unwrap();
+                };
+                // Outputs 0, 0, 1 and 1: only the third one is a change.
+                run("4", "0");
+                // Same output as the last one kept in the state file, then a change.
+                run("2", "3");
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "changed\nchanged\n");
+        let hash = io::read_to_string(File::open(change_state_file).unwrap()).unwrap();
+        assert_eq!(hash, output_hash(b"2\n"));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_output_encoding() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "2",
+                    "--output-encoding",
+                    "latin1",
+                    "--command-output-to-env",
+                    "--",
+                    &format!(
+                        "echo ${{CRONTHAT_PREV_OUTPUT:-unset}} >> {:?}; printf 'caf\\351\\n'",
+                        tmp_path
+                    ),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "unset\ncafé\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn resolve_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = std::fs::canonicalize(tmp.path()).unwrap();
+        std::fs::create_dir(dir.join("releases")).unwrap();
+        std::fs::write(dir.join("releases/holidays.txt"), "").unwrap();
+        std::os::unix::fs::symlink(dir.join("releases"), dir.join("current")).unwrap();
+
+        let holidays = dir.join("current/holidays.txt");
+        let state_file = dir.join("current/state.json");
+        let parse = |follow_symlinks: bool| {
+            let mut args = vec![
+                "cronthat",
+                CRON_EVERY_S,
+                "--holidays",
+                holidays.to_str().unwrap(),
+                "--state-file",
+                state_file.to_str().unwrap(),
+            ];
+            if follow_symlinks {
+                args.push("--follow-symlinks");
+            }
+            args.push("true");
+            CronThat::try_parse_from(args).unwrap()
+        };
+        let mut cli = parse(true);
+        cli.resolve_symlinks().unwrap();
+        assert_eq!(cli.holidays, Some(dir.join("releases/holidays.txt")));
+        assert_eq!(cli.state_file, Some(dir.join("releases/state.json")));
+
+        let cli = parse(false);
+        assert_eq!(cli.holidays, Some(holidays));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ssh_stage() {
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--ssh",
+            "deploy@web1",
+            "--ssh-opts",
+            "-p 2222",
+            "true",
+        ])
+        .unwrap();
+        let env = HashMap::from([("CRONTHAT_ITERATION".to_string(), "it's 3".to_string())]);
+        let stage = cli.ssh_stage("deploy@web1", r#"echo "$CRONTHAT_ITERATION" 'a  b'"#, &env);
+        assert!(
+            stage.starts_with("ssh -p 2222 'deploy@web1' -- '"),
+            "{}",
+            stage
+        );
+
+        // Run the remote command like sshd would, in a blank environment.
+        let script = format!(
+            r#"ssh() {{ while [ "$1" != "--" ]; do shift; done; env -i PATH="$PATH" sh -c "$2"; }}; {}"#,
+            stage
+        );
+        let output = std::process::Command::new("sh")
+            .args(["-c", &script])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "it's 3 a  b\n");
+    }
+
+    #[test]
+    fn previous_output_capped() {
+        assert_eq!(previous_output("  cursor \n"), "cursor");
+        let long = "é".repeat(PREV_OUTPUT_MAX_BYTES);
+        let kept = previous_output(&long);
+        assert_eq!(kept.len(), PREV_OUTPUT_MAX_BYTES);
+        assert!(long.starts_with(&kept));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_env_from_file() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let mut secret = tempfile::NamedTempFile::new().unwrap();
+        writeln!(secret, "s3cr3t").unwrap();
+        let env_from_file = format!("CRONTHAT_TEST_SECRET={}", secret.path().display());
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "1",
+                    "--env-from-file",
+                    &env_from_file,
+                    "--",
+                    &format!("echo $CRONTHAT_TEST_SECRET >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "s3cr3t\n");
+        assert!(std::env::var("CRONTHAT_TEST_SECRET").is_err());
+    }
+
+    #[test]
+    fn output_prefix_trimmed() {
+        assert_eq!(output_prefix("echo '  abc123  '").unwrap(), "abc123");
+        assert_eq!(
+            output_prefix("exit 2").unwrap_err().to_string(),
+            "--output-prefix-command 'exit 2' exited with status 2"
+        );
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_exhausted_schedule() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let command = format!("echo reboot >> {:?}", tmp_path);
+                let cli =
+                    CronThat::try_parse_from(vec!["cronthat", "@reboot", "--", &command]).unwrap();
+                let state = cli.run(&Shutdown::default()).unwrap();
+                assert_eq!(state.runs, 1);
+
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    "0 0 0 1 1 * 2020",
+                    "--loop-forever",
+                    "--",
+                    "true",
+                ])
+                .unwrap();
+                let err = cli.run(&Shutdown::default()).err().unwrap();
+                assert_eq!(
+                    err.to_string(),
+                    "the schedule has no upcoming execution left (--loop-forever)"
+                );
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "reboot\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_failure_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let failure_path = dir.path().join("failure.json");
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let failure_path = failure_path.clone();
+            spawn_blocking(move || {
+                let run = |command: &str| {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--now",
+                        "--repetitions",
+                        "1",
+                        "--failure-file",
+                        failure_path.to_str().unwrap(),
+                        "--",
+                        command,
+                    ])
+                    .unwrap();
+                    cli.execute().unwrap();
+                };
+                run("exit 3");
+                let content = io::read_to_string(File::open(&failure_path).unwrap()).unwrap();
+                let failure: serde_json::Value = serde_json::from_str(&content).unwrap();
+                assert_eq!(failure["exit_code"], 3);
+                assert_eq!(failure["command"], "exit 3");
+                assert_eq!(failure["run_number"], 1);
+                assert!(failure["timestamp"].is_string());
+
+                run("true");
+                assert!(!failure_path.exists());
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_count_from() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "2",
+                    "--count-from",
+                    "100",
+                    "--",
+                    &format!("echo $CRONTHAT_ITERATION {{iteration}} >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "100 100\n101 101\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_pipe_to() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let pipe_to = format!("grep hi >> {:?}", tmp_path);
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "1",
+                    "--stop-on-error",
+                    "--pipe-to",
+                    &pipe_to,
+                    "--",
+                    "echo",
+                    "hi",
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+
+                // The downstream command fails while the scheduled one succeeds.
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--stop-on-error",
+                    "--pipe-to",
+                    "grep nope",
+                    "--",
+                    "echo",
+                    "hi",
+                ])
+                .unwrap();
+                cli.execute().expect_err("must stop on error");
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "hi\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_resume_from_state_file() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let state_dir = tempfile::tempdir().unwrap();
+        let state_path = state_dir.path().join("state.json");
+        State {
+            runs: 1,
+            ..Default::default()
+        }
+        .save(&state_path)
+        .unwrap();
+
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            let state_path = state_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--state-file",
+                    state_path.to_str().unwrap(),
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\n");
+        let state = State::load(&state_path).unwrap();
+        assert_eq!(state.runs, 2);
+        assert!(state.last_run.is_some());
+    }
+
+    #[test]
+    fn cronthat_run_on_start_if_overdue() {
+        let state_file = tempfile::NamedTempFile::new().unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "0 0 12 * * *",
+            "--state-file",
+            state_file.path().to_str().unwrap(),
+            "--run-on-start-if-overdue",
+            "--",
+            "true",
+        ])
+        .unwrap();
+        let schedule = cron::Schedule::from_str("0 0 12 * * *").unwrap();
+        let state = |last_run: Option<DateTime<Local>>| State {
+            last_run,
+            ..Default::default()
+        };
+
+        assert!(cli.overdue_run(&state(None), &schedule));
+        let last_noon = schedule.after(&(Local::now() - TimeDelta::days(1))).next();
+        assert!(cli.overdue_run(
+            &state(last_noon.map(|noon| noon - TimeDelta::minutes(1))),
+            &schedule
+        ));
+        assert!(!cli.overdue_run(&state(last_noon), &schedule));
+        assert!(!cli.overdue_run(&state(Some(Local::now())), &schedule));
+
+        // Overdue since the day before yesterday, the immediate execution is the last one.
+        State {
+            runs: 2,
+            ..state(Some(Local::now() - TimeDelta::days(2)))
+        }
+        .save(state_file.path())
+        .unwrap();
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "0 0 12 * * *",
+            "--state-file",
+            state_file.path().to_str().unwrap(),
+            "--run-on-start-if-overdue",
+            "--repetitions",
+            "3",
+            "--",
+            &format!("echo overdue >> {:?}", tmp.path()),
+        ])
+        .unwrap();
+        let state = cli.run(&Shutdown::default()).unwrap();
+        assert_eq!(state.runs, 3);
+        let content = io::read_to_string(File::open(tmp.path()).unwrap()).unwrap();
+        assert_eq!(content, "overdue\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_run_at_startup_if_missed() {
+        for (last_success, expected) in [
+            (None, "helloworld\n"),
+            (Some(TimeDelta::hours(1)), "helloworld\n"),
+            (Some(TimeDelta::minutes(1)), ""),
+        ] {
+            let tmp = tempfile::NamedTempFile::new().unwrap();
+            let tmp_path = tmp.path().to_path_buf();
+            let state_dir = tempfile::tempdir().unwrap();
+            let state_path = state_dir.path().join("state.json");
+            State {
+                runs: 1,
+                last_success: last_success.map(|ago| Local::now() - ago),
+                ..Default::default()
+            }
+            .save(&state_path)
+            .unwrap();
+
+            let timeout_duration = tokio::time::Duration::from_secs(2);
+            timeout(timeout_duration, async {
+                let tmp_path = tmp_path.clone();
+                let state_path = state_path.clone();
+                spawn_blocking(move || {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--repetitions",
+                        "1",
+                        "--state-file",
+                        state_path.to_str().unwrap(),
+                        "--run-at-startup-if-missed",
+                        "5m",
+                        "--",
+                        &format!("echo helloworld >> {:?}", tmp_path),
+                    ])
+                    .unwrap();
+                    cli.execute().unwrap();
+                })
+                .await
+                .unwrap();
+            })
+            .await
+            .expect("timed out");
+
+            let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+            assert_eq!(content, expected, "{:?}", last_success);
+        }
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_config_jobs() {
+        let first = tempfile::NamedTempFile::new().unwrap();
+        let second = tempfile::NamedTempFile::new().unwrap();
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            config,
+            r#"
+            [[jobs]]
+            name = "first"
+            cron_expression = "{}"
+            repetitions = 2
+            command = ["echo first >> {}"]
+
+            [[jobs]]
+            cron_expression = "{}"
+            repetitions = 1
+            command = ["echo", "second", ">>", "{}"]
+            "#,
+            CRON_EVERY_S,
+            first.path().display(),
+            CRON_EVERY_S,
+            second.path().display()
+        )
+        .unwrap();
+        let config_path = config.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    "--config",
+                    config_path.to_str().unwrap(),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(first.path()).unwrap()).unwrap();
+        assert_eq!(content, "first\nfirst\n");
+        let content = io::read_to_string(File::open(second.path()).unwrap()).unwrap();
+        assert_eq!(content, "second\n");
+    }
+
+    #[test]
+    fn cronthat_validate_config() {
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            config,
+            r#"
+            [[jobs]]
+            name = "valid"
+            cron_expression = "{}"
+            command = ["true"]
+
+            [[jobs]]
+            name = "every 0th"
+            cron_expression = "{}"
+            repetitions = 2
+            every_nth = 0
+            command = ["true"]
+
+            [[jobs]]
+            name = "out of range"
+            cron_expression = "0 0 25 * * *"
+            command = ["true"]
+            "#,
+            CRON_EVERY_S, CRON_EVERY_S
+        )
+        .unwrap();
+        let config_path = config.path().to_str().unwrap();
+
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "--config",
+            config_path,
+            "--validate-config",
+        ])
+        .unwrap();
+        let err = cli.run(&Shutdown::default()).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("2 job(s) invalid in {:?}", config.path())
+        );
+
+        // Syntax errors are reported with their line and column.
+        writeln!(config, "[[jobs]\n").unwrap();
+        let err = cli.run(&Shutdown::default()).err().unwrap();
+        assert!(format!("{:#}", err).contains("line 18"), "{:#}", err);
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_watch_config() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let config = tempfile::NamedTempFile::new().unwrap();
+        let write_config = |cron_expression: &str, word: &str| {
+            std::fs::write(
+                config.path(),
+                format!(
+                    "[[jobs]]\ncron_expression = \"{}\"\ncommand = [\"echo {} >> {}\"]\n",
+                    cron_expression,
+                    word,
+                    tmp.path().display()
+                ),
+            )
+            .unwrap();
+        };
+        write_config(CRON_EVERY_S, "first");
+        let config_path = config.path().to_path_buf();
+        let shutdown = Shutdown::default();
+
+        let timeout_duration = tokio::time::Duration::from_secs(6);
+        let run_shutdown = shutdown.clone();
+        let handle = spawn_blocking(move || {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                "--config",
+                config_path.to_str().unwrap(),
+                "--watch-config",
+            ])
+            .unwrap();
+            cli.run(&run_shutdown).unwrap()
+        });
+        tokio::time::sleep(tokio::time::Duration::from_millis(1_500)).await;
+        // Invalid content keeps the running job.
+        write_config("0 0 25 * * *", "invalid");
+        tokio::time::sleep(tokio::time::Duration::from_millis(1_000)).await;
+        write_config(CRON_EVERY_S, "second");
+        tokio::time::sleep(tokio::time::Duration::from_millis(2_000)).await;
+        shutdown.trigger();
+        let state = timeout(timeout_duration, handle)
+            .await
+            .expect("timed out")
+            .unwrap();
+
+        let content = io::read_to_string(File::open(tmp.path()).unwrap()).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines.len(), state.runs);
+        assert!(lines.iter().filter(|line| **line == "first").count() >= 2);
+        assert!(lines.contains(&"second"));
+        assert!(!lines.contains(&"invalid"));
+        assert!(lines.is_sorted_by_key(|line| *line == "second"));
+    }
+
+    #[test]
+    fn cronthat_no_now_overrides_config() {
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            config,
+            r#"
+            [[jobs]]
+            cron_expression = "{}"
+            command = ["true"]
+            now = true
+            "#,
+            CRON_EVERY_S
+        )
+        .unwrap();
+        let config_path = config.path().to_str().unwrap();
+
+        let cli = CronThat::try_parse_from(vec!["cronthat", "--config", config_path]).unwrap();
+        assert!(cli.load_config(config.path()).unwrap().jobs[0].cronthat.now);
+
+        let cli = CronThat::try_parse_from(vec!["cronthat", "--config", config_path, "--no-now"])
+            .unwrap();
+        assert!(!cli.load_config(config.path()).unwrap().jobs[0].cronthat.now);
+
+        let cli =
+            CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--now", "--no-now", "true"])
+                .unwrap();
+        assert!(!cli.now);
+    }
+
+    #[test]
+    fn cronthat_config_log_buffer_lines_default() {
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            config,
+            r#"
+            [[jobs]]
+            cron_expression = "{}"
+            command = ["true"]
+
+            [[jobs]]
+            cron_expression = "{}"
+            command = ["true"]
+            log_buffer_lines = 5
+            "#,
+            CRON_EVERY_S, CRON_EVERY_S
+        )
+        .unwrap();
+        let config_path = config.path().to_str().unwrap();
+
+        let cli = CronThat::try_parse_from(vec!["cronthat", "--config", config_path]).unwrap();
+        assert_eq!(cli.log_buffer_lines, 100);
+        let jobs = cli.load_config(config.path()).unwrap().jobs;
+        assert_eq!(jobs[0].cronthat.log_buffer_lines, 100);
+        assert_eq!(jobs[1].cronthat.log_buffer_lines, 5);
+    }
+
+    #[test]
+    fn cronthat_expand_template() {
+        let datetime = Local.with_ymd_and_hms(2025, 1, 2, 15, 4, 0).unwrap();
+        assert_eq!(
+            expand_template(r#"{"date": "{date}", "time": "{time}"}"#, &datetime),
+            r#"{"date": "2025-01-02", "time": "15:04:00"}"#
+        );
+        assert_eq!(
+            expand_template("{scheduled} {timestamp}", &datetime),
+            format!("{} {}", datetime.to_rfc3339(), datetime.timestamp())
+        );
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_stdin_template() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--stdin-template",
+                    "tick {timestamp}\n",
+                    "--",
+                    &format!("cat >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        let timestamps: Vec<i64> = content
+            .lines()
+            .map(|line| line.strip_prefix("tick ").unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(timestamps[1] - timestamps[0], 1);
+    }
+
+    #[test]
+    fn cronthat_iso_dates() {
+        let datetime = Local.with_ymd_and_hms(2025, 1, 2, 15, 4, 0).unwrap();
+        let cli = CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "true"]).unwrap();
+        assert_eq!(cli.format_time(&datetime), datetime.to_string());
+        let cli = CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--iso-dates", "true"])
+            .unwrap();
+        assert_eq!(
+            cli.format_time(&datetime),
+            datetime.to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+        );
+        assert!(cli
+            .format_time(&datetime)
+            .starts_with("2025-01-02T15:04:00.000"));
+    }
+
+    #[test]
+    fn cronthat_timestamp_format() {
+        let datetime = Local.with_ymd_and_hms(2025, 1, 2, 15, 4, 0).unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--iso-dates",
+            "--timestamp-format",
+            "%H:%M:%S",
+            "true",
+        ])
+        .unwrap();
+        assert_eq!(cli.format_time(&datetime), "15:04:00");
+        assert!(CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--timestamp-format",
+            "%Q",
+            "true",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn cronthat_explain_wait() {
+        let datetime = Local.with_ymd_and_hms(2025, 1, 2, 15, 4, 0).unwrap();
+        let wait = TimeDelta::seconds(252);
+        let cli =
+            CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "-n", "10", "true"]).unwrap();
+        assert_eq!(
+            cli.explain_wait(wait, &datetime, 3),
+            format!("Sleeping 4m12s until {} (run 3 of 10)", datetime)
+        );
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--timestamp-format",
+            "%d/%m %H:%M",
+            "--until",
+            "2025-01-03 00:00:00",
+            "true",
+        ])
+        .unwrap();
+        assert_eq!(
+            cli.explain_wait(wait, &datetime, 1),
+            "Sleeping 4m12s until 02/01 15:04 (run 1, until 03/01 00:00)"
+        );
+        let cli = CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--iso-dates", "true"])
+            .unwrap();
+        assert_eq!(
+            cli.explain_wait(wait, &datetime, 1),
+            format!(
+                "Sleeping 4m12s until {} (run 1)",
+                datetime.to_rfc3339_opts(chrono::SecondsFormat::Millis, false)
+            )
+        );
+    }
+
+    #[test]
+    fn cronthat_schedule_offset() {
+        let schedule = cron::Schedule::from_str("0 0 * * * *").unwrap();
+        for (offset, minute) in [("5m", 5), ("-5m", 55), ("0s", 0)] {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                "0 0 * * * *",
+                "--schedule-offset",
+                offset,
+                "true",
+            ])
+            .unwrap();
+            let now = Local::now();
+            let first = cli.next_fire_time(&schedule, None).unwrap();
+            let second = cli.next_fire_time(&schedule, Some(&first)).unwrap();
+            let fire_times = [first, second];
+            assert_eq!(fire_times[0].minute(), minute, "{}", offset);
+            assert!(fire_times[0] > now);
+            assert!(fire_times[0] - now <= TimeDelta::hours(1));
+            assert_eq!(fire_times[1] - fire_times[0], TimeDelta::hours(1));
+        }
+    }
+
+    #[test]
+    fn cronthat_min_interval() {
+        for (cron_expression, accepted) in [
+            (CRON_EVERY_S, false),
+            ("*/10 * * * * *", true),
+            ("0,5 * * * * *", false),
+        ] {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                cron_expression,
+                "--min-interval",
+                "10s",
+                "true",
+            ])
+            .unwrap();
+            let schedule = cron::Schedule::from_str(cron_expression).unwrap();
+            assert_eq!(
+                cli.check_min_interval(&schedule).is_ok(),
+                accepted,
+                "{}",
+                cron_expression
+            );
+        }
+    }
+
+    #[test]
+    fn cronthat_min_interval_cron_timezone() {
+        // 01:00 and 03:00 in Paris are only an hour apart on the day clocks go forward.
+        let cron_expression = "0 0 1,3 * * *";
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            cron_expression,
+            "--cron-timezone",
+            "Europe/Paris",
+            "--min-interval",
+            "90m",
+            "true",
+        ])
+        .unwrap();
+        let schedule = cron::Schedule::from_str(cron_expression).unwrap();
+        let from = Local.with_ymd_and_hms(2026, 3, 20, 12, 0, 0).unwrap();
+        assert_eq!(
+            cli.shortest_interval(&schedule, from),
+            Some(TimeDelta::hours(1))
+        );
+    }
+
+    #[test]
+    fn cronthat_execute_lock_file() {
+        let lock_dir = tempfile::tempdir().unwrap();
+        let lock_path = lock_dir.path().join("cronthat.lock");
+        let _held = LockFile::acquire(&lock_path).unwrap();
+
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--lock-file",
+            lock_path.to_str().unwrap(),
+            "--",
+            "true",
+        ])
+        .unwrap();
+        cli.execute().expect_err("lock must be held");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_concurrent_safe() {
+        let lock_dir = tempfile::tempdir().unwrap();
+        let lock_path = lock_dir.path().join("{hash}.lock");
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--concurrent-safe",
+                    "--lock-file",
+                    lock_path.to_str().unwrap(),
+                    "--",
+                    "true",
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        assert_eq!(std::fs::read_dir(lock_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn cronthat_negative_durations() {
+        for option in [
+            "--timeout-per-run",
+            "--timeout-total",
+            "--batch-window",
+            "--dedup-window",
+            "--late-threshold",
+            "--min-interval",
+        ] {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                CRON_EVERY_S,
+                &format!("{}=-5s", option),
+                "--",
+                "true",
+            ])
+            .unwrap();
+            let err = cli.check_args().unwrap_err();
+            assert_eq!(err.to_string(), format!("negative {} -5s", option));
+        }
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--schedule-offset=-5s",
+            "--",
+            "true",
+        ])
+        .unwrap();
+        cli.check_args().unwrap();
+    }
+
+    #[test]
+    fn cronthat_concurrent_safe_requires_hash() {
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--concurrent-safe",
+            "--lock-file",
+            "/tmp/slot.lock",
+            "--",
+            "true",
+        ])
+        .unwrap();
+        let err = cli.check_args().unwrap_err();
+        assert!(err.to_string().contains("has no \"{hash}\""), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_retry_scope() {
+        for (scope, expected_attempts) in [("tick", 4), ("global", 3)] {
+            let tmp = tempfile::NamedTempFile::new().unwrap();
+            let tmp_path = tmp.path().to_path_buf();
+
+            let timeout_duration = tokio::time::Duration::from_secs(3);
+            timeout(timeout_duration, async {
+                let tmp_path = tmp_path.clone();
+                spawn_blocking(move || {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--repetitions",
+                        "2",
+                        "--retry",
+                        "1",
+                        "--retry-scope",
+                        scope,
+                        "--",
+                        &format!("echo attempt >> {:?}; exit 1", tmp_path),
+                    ])
+                    .unwrap();
+                    cli.execute().unwrap();
+                })
+                .await
+                .unwrap();
+            })
+            .await
+            .expect("timed out");
+
+            let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+            assert_eq!(content.lines().count(), expected_attempts, "{}", scope);
+        }
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_success_codes() {
+        let timeout_duration = tokio::time::Duration::from_secs(6);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let run = |command: &str| {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--repetitions",
+                        "1",
+                        "--stop-on-error",
+                        "--success-codes",
+                        "0,2,75",
+                        "--",
+                        command,
+                    ])
+                    .unwrap();
+                    cli.run(&Shutdown::default())
+                };
+                for command in ["true", "exit 2", "exit 75"] {
+                    let state = run(command).unwrap();
+                    assert_eq!(state.failures, 0, "{}", command);
+                }
+                let err = run("exit 1").err().unwrap();
+                assert_eq!(
+                    err.to_string(),
+                    "command exited with status code 1, not in --success-codes"
+                );
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_retry_codes() {
+        for (code, expected_attempts) in [("2", 1), ("75", 3)] {
+            let tmp = tempfile::NamedTempFile::new().unwrap();
+            let tmp_path = tmp.path().to_path_buf();
+
+            let timeout_duration = tokio::time::Duration::from_secs(3);
+            timeout(timeout_duration, async {
+                let tmp_path = tmp_path.clone();
+                spawn_blocking(move || {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--repetitions",
+                        "1",
+                        "--retry",
+                        "2",
+                        "--retry-codes",
+                        "1,75",
+                        "--",
+                        &format!("echo attempt >> {:?}; exit {}", tmp_path, code),
+                    ])
+                    .unwrap();
+                    cli.execute().unwrap();
+                })
+                .await
+                .unwrap();
+            })
+            .await
+            .expect("timed out");
+
+            let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+            assert_eq!(content.lines().count(), expected_attempts, "exit {}", code);
+        }
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_command_timeout_exit_code() {
+        for (code, expected_attempts) in [("2", 1), ("3", 3)] {
+            let tmp = tempfile::NamedTempFile::new().unwrap();
+            let tmp_path = tmp.path().to_path_buf();
+
+            let timeout_duration = tokio::time::Duration::from_secs(3);
+            timeout(timeout_duration, async {
+                let tmp_path = tmp_path.clone();
+                spawn_blocking(move || {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--repetitions",
+                        "1",
+                        "--command-timeout-exit-code",
+                        "3",
+                        "--retry",
+                        "2",
+                        "--retry-codes",
+                        "124",
+                        "--",
+                        &format!("echo attempt >> {:?}; exit {}", tmp_path, code),
+                    ])
+                    .unwrap();
+                    cli.execute().unwrap();
+                })
+                .await
+                .unwrap();
+            })
+            .await
+            .expect("timed out");
+
+            let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+            assert_eq!(content.lines().count(), expected_attempts, "exit {}", code);
+        }
+
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--command-timeout-exit-code",
+            "0",
+            "true",
+        ])
+        .unwrap();
+        assert!(cli.check_args().is_err());
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_after_command() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--stop-on-error",
+                    "--after-command",
+                    &format!(
+                        "echo $CRONTHAT_EXIT_CODE $CRONTHAT_RUN_NUMBER >> {:?}; exit 4",
+                        tmp_path
+                    ),
+                    "--",
+                    "true",
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "0 1\n0 2\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_trace_id() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--retry",
+                    "1",
+                    "--trace-id",
+                    "--",
+                    &format!("echo $CRONTHAT_TRACE_ID >> {:?}; exit 1", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        let trace_ids: Vec<&str> = content.lines().collect();
+        assert_eq!(trace_ids.len(), 4);
+        assert_eq!(trace_ids[0].len(), 36);
+        assert_eq!(trace_ids[0], trace_ids[1], "stable across retries");
+        assert_eq!(trace_ids[2], trace_ids[3], "stable across retries");
+        assert_ne!(trace_ids[0], trace_ids[2], "unique per execution");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_last_exit_code() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--",
+                    &format!(
+                        "echo ${{CRONTHAT_LAST_EXIT_CODE:-unset}} >> {:?}; exit 3",
+                        tmp_path
+                    ),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "unset\n3\n");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_budget_exhausted() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let mut budget = tempfile::NamedTempFile::new().unwrap();
+        write!(budget, "100").unwrap();
+        let budget_path = budget.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            let budget_path = budget_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--budget-file",
+                    budget_path.to_str().unwrap(),
+                    "--budget-max-ms",
+                    "400",
+                    "--",
+                    &format!("echo helloworld >> {:?}; sleep 0.2", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\nhelloworld\n");
+        let spent: i64 = std::fs::read_to_string(budget_path)
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(spent >= 400);
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_expect_json_field() {
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                for (output, succeeds) in [(r#"{"status":"ok"}"#, true), ("{}", false)] {
+                    let cli = CronThat::try_parse_from(vec![
+                        "cronthat",
+                        CRON_EVERY_S,
+                        "--repetitions",
+                        "1",
+                        "--stop-on-error",
+                        "--expect-json-field",
+                        "status=ok",
+                        "--",
+                        &format!("echo '{}'", output),
+                    ])
+                    .unwrap();
+                    assert_eq!(cli.execute().is_ok(), succeeds, "{}", output);
+                }
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_schedule_file() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let mut schedule_file = tempfile::NamedTempFile::new().unwrap();
+        write!(schedule_file, "{}", CRON_EVERY_S).unwrap();
+        let schedule_path = schedule_file.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(8);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    "--schedule-file",
+                    schedule_path.to_str().unwrap(),
+                    "--repetitions",
+                    "3",
+                    "--",
+                    "date",
+                    &format!(
+                        "+%s >> {:?}; echo '*/2 * * * * *' > {:?}",
+                        tmp_path, schedule_path
+                    ),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        let timestamps: Vec<i64> = content.lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(timestamps.len(), 3);
+        assert_eq!(timestamps[1] % 2, 0);
+        assert_eq!(timestamps[2] - timestamps[1], 2);
+    }
+
+    #[test]
+    fn cronthat_reload_invalid_schedule_file() {
+        let mut schedule_file = tempfile::NamedTempFile::new().unwrap();
+        write!(schedule_file, "not a cron expression").unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "--schedule-file",
+            schedule_file.path().to_str().unwrap(),
+            "true",
+        ])
+        .unwrap();
+
+        let mut cron_expression = CRON_EVERY_S.to_string();
+        let mut schedule = cron::Schedule::from_str(CRON_EVERY_S).unwrap();
+        assert!(!cli.reload_schedule(&mut cron_expression, &mut schedule));
+        assert_eq!(cron_expression, CRON_EVERY_S);
+    }
+
+    #[test]
+    fn cronthat_cron_expression_file_comments() {
+        let mut schedule_file = tempfile::NamedTempFile::new().unwrap();
+        write!(
+            schedule_file,
+            "# Every weekday morning\n\n  0 30 9 * * MON-FRI  \n0 0 12 * * *\n"
+        )
+        .unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "--cron-expression-file",
+            schedule_file.path().to_str().unwrap(),
+            "true",
+        ])
+        .unwrap();
+        assert_eq!(cli.read_cron_expression().unwrap(), "0 30 9 * * MON-FRI");
+
+        let mut schedule_file = tempfile::NamedTempFile::new().unwrap();
+        writeln!(schedule_file, "# Nothing yet").unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "--cron-expression-file",
+            schedule_file.path().to_str().unwrap(),
+            "true",
+        ])
+        .unwrap();
+        assert!(cli.read_cron_expression().is_err());
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_skip_first() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let start = std::time::Instant::now();
+
+        let timeout_duration = tokio::time::Duration::from_secs(6);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--skip-first",
+                    "2",
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\nhelloworld\n");
+        assert!(start.elapsed() >= std::time::Duration::from_secs(3));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_every_nth() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(6);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--every-nth",
+                    "2",
+                    "--",
+                    &format!("date +%s >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        let timestamps: Vec<i64> = content.lines().map(|line| line.parse().unwrap()).collect();
+        assert_eq!(timestamps.len(), 2);
+        assert_eq!(timestamps[1] - timestamps[0], 2);
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_dedup_window() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = timed_command(dir.path());
+
+        let timeout_duration = tokio::time::Duration::from_secs(5);
+        timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "2",
+                    "--dedup-window",
+                    "1500ms",
+                    "--",
+                    &command,
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let runs = run_times(dir.path());
+        assert_eq!(runs.len(), 2);
+        assert!(runs[1] - runs[0] >= 1_450, "{:?}", runs);
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_cycle() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(5);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let commands: Vec<String> = ["first", "second", "third"]
+                    .iter()
+                    .map(|word| format!("echo {} >> {:?}", word, tmp_path))
+                    .collect();
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--repetitions",
+                    "4",
+                    "--cycle",
+                    "--command",
+                    &commands[0],
+                    "--command",
+                    &commands[1],
+                    "--command",
+                    &commands[2],
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "first\nsecond\nthird\nfirst\n");
+
+        assert!(
+            CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--cycle", "--", "echo"])
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_max_consecutive_skips() {
+        let timeout_duration = tokio::time::Duration::from_secs(5);
+        let err = timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--every-nth",
+                    "100",
+                    "--max-consecutive-skips",
+                    "2",
+                    "--",
+                    "echo",
+                    "helloworld",
+                ])
+                .unwrap();
+                cli.execute().unwrap_err()
+            })
+            .await
+            .unwrap()
+        })
+        .await
+        .expect("timed out");
+
+        assert!(err
+            .to_string()
+            .starts_with("2 executions in a row were skipped"));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_until_file_contains() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("pipeline.log");
+        let until_file_contains = format!("{}:(?m)^DONE$", log_path.display());
+
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        let state = timeout(timeout_duration, async {
+            let log_path = log_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--until-file-contains",
+                    &until_file_contains,
+                    "--",
+                    &format!(
+                        "echo run >> {:?}; [ $CRONTHAT_ITERATION = 1 ] && echo DONE >> {:?}; true",
+                        log_path, log_path
+                    ),
+                ])
+                .unwrap();
+                cli.run(&Shutdown::default()).unwrap()
+            })
+            .await
+            .unwrap()
+        })
+        .await
+        .expect("timed out");
+
+        assert_eq!(state.runs, 2);
+    }
+
+    #[test]
+    fn cronthat_max_skips() {
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--max-skips",
+            "3",
+            "--",
+            "echo",
+        ])
+        .unwrap();
+        assert_eq!(cli.max_consecutive_skips, Some(3));
+    }
+
+    #[test]
+    fn cronthat_every_x_occurrences() {
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--every-x-occurrences",
+            "5",
+            "--",
+            "echo",
+        ])
+        .unwrap();
+        assert_eq!(cli.every_nth, Some(5));
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_confirm_yes() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "1",
+                    "--confirm",
+                    "--yes",
+                    "--",
+                    &format!("echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\n");
+    }
 
-    /// When to stop (mutually exclusive with --repetitions)
-    #[clap(short, long, value_parser = parse_date_time)]
-    until: Option<DateTime<Local>>,
+    #[tokio::test]
+    async fn cronthat_execute_batch_window() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
 
-    /// Schedule a first execution immediately
-    #[clap(short('w'), long)]
-    now: bool,
-}
+        let timeout_duration = tokio::time::Duration::from_secs(6);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "2",
+                    "--batch-window",
+                    "1.5s",
+                    "--",
+                    &format!(
+                        "echo $CRONTHAT_COALESCED_TICKS $(date +%s) >> {:?}",
+                        tmp_path
+                    ),
+                ])
+                .unwrap();
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
 
-fn parse_date_time(value: &str) -> Result<DateTime<Local>> {
-    let dt = NaiveDateTime::parse_from_str(value, DATETIME_FORMAT)?
-        .and_local_timezone(Local::now().timezone())
-        .single()
-        .context("cannot parse with timezone")?;
-    Ok(dt)
-}
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        let runs: Vec<(&str, i64)> = content
+            .lines()
+            .map(|line| {
+                let (coalesced, timestamp) = line.split_once(' ').unwrap();
+                (coalesced, timestamp.parse().unwrap())
+            })
+            .collect();
+        assert_eq!(runs.len(), 2);
+        assert_eq!((runs[0].0, runs[1].0), ("0", "1"));
+        assert_eq!(runs[1].1 - runs[0].1, 2);
+    }
 
-impl CronThat {
-    pub fn execute(&self) -> Result<()> {
-        self.check_args()?;
-        let schedule =
-            Schedule::from_str(&self.cron_expression).context("invalid cron expression")?;
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn cronthat_execute_control_fifo() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let fifo_dir = tempfile::tempdir().unwrap();
+        let fifo_path = fifo_dir.path().join("control");
 
-        if self.now {
-            self.spawn_command()?;
-        }
+        let timeout_duration = tokio::time::Duration::from_secs(5);
+        timeout(timeout_duration, async {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                "0 0 0 1 1 *",
+                "--control-fifo",
+                fifo_path.to_str().unwrap(),
+                "--",
+                &format!("echo helloworld >> {:?}", tmp_path),
+            ])
+            .unwrap();
+            let cronthat = spawn_blocking(move || {
+                // Not execute() so that quitting doesn't shut down the other tests.
+                cli.run(&Shutdown::default()).unwrap()
+            });
 
-        for (i, datetime) in schedule
-            .upcoming(Local::now().timezone())
-            .into_iter()
-            .enumerate()
-        {
-            if self.must_stop(i) {
-                break;
+            while !fifo_path.exists() {
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
             }
-
-            let now: DateTime<Local> = Local::now();
-            let wait = datetime.signed_duration_since(now);
-            let succeeded = if wait > Duration::zero() {
-                sleep(wait.to_std()?);
-                self.spawn_command()?
-            } else {
-                self.spawn_command()?
-            };
-
-            if !succeeded {
-                if self.stop_on_error {
-                    bail!("command exited with non-zero status code");
-                } else {
-                    println!("warning: command exited with non-zero status code");
-                    println!();
-                }
+            let mut fifo = File::options().write(true).open(&fifo_path).unwrap();
+            writeln!(fifo, "run-now").unwrap();
+            while std::fs::read_to_string(&tmp_path).unwrap().is_empty() {
+                tokio::time::sleep(tokio::time::Duration::from_millis(50)).await;
             }
-        }
+            writeln!(fifo, "dance\nquit").unwrap();
+            let state = cronthat.await.unwrap();
+            assert_eq!(state.runs, 1);
+        })
+        .await
+        .expect("timed out");
 
-        Ok(())
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\n");
     }
 
-    fn spawn_command(&self) -> Result<bool> {
-        println!("{} -- Spawning command", Local::now());
-        let mut options = ScriptOptions::new();
-        options.output_redirection = IoOptions::Inherit;
-        let (status, _, _) = run_script::run(self.command.join(" ").as_str(), &vec![], &options)?;
-        Ok(status == 0)
+    #[tokio::test]
+    async fn cronthat_execute_timeout_per_run() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        let err = timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--repetitions",
+                    "1",
+                    "--stop-on-error",
+                    "--timeout-per-run",
+                    "500ms",
+                    "--",
+                    &format!("sleep 5; echo helloworld >> {:?}", tmp_path),
+                ])
+                .unwrap();
+                cli.execute().unwrap_err()
+            })
+            .await
+            .unwrap()
+        })
+        .await
+        .expect("timed out");
+
+        assert_eq!(err.to_string(), "command exited with non-zero status code");
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "");
     }
 
-    fn check_args(&self) -> Result<()> {
-        if self.repetitions.is_some() && self.until.is_some() {
-            bail!("--repetitions and --until are mutually exclusive");
-        }
+    #[tokio::test]
+    async fn cronthat_execute_wrap_in_timeout_cmd() {
+        let start = std::time::Instant::now();
 
-        if self.command.is_empty() {
-            bail!("no command to execute");
-        }
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        let err = timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--now",
+                    "--stop-on-error",
+                    "--wrap-in-timeout-cmd",
+                    "timeout 0.5",
+                    "--",
+                    "sleep",
+                    "5",
+                ])
+                .unwrap();
+                cli.execute().unwrap_err()
+            })
+            .await
+            .unwrap()
+        })
+        .await
+        .expect("timed out");
 
-        Ok(())
+        assert_eq!(err.to_string(), "command exited with non-zero status code");
+        assert!(start.elapsed() < std::time::Duration::from_secs(3));
     }
 
-    fn must_stop(&self, i: usize) -> bool {
-        if self.repetitions.is_none() && self.until.is_none() {
-            false
-        } else {
-            if self.repetitions.is_some() {
-                i >= self.repetitions.unwrap()
-            } else {
-                Local::now() > self.until.unwrap()
-            }
-        }
-    }
-}
+    #[tokio::test]
+    async fn cronthat_execute_max_memory() {
+        let timeout_duration = tokio::time::Duration::from_secs(2);
+        let err = timeout(timeout_duration, async {
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    "0 0 0 1 1 *",
+                    "--max-memory",
+                    "1024",
+                    "--",
+                    "echo",
+                    "helloworld",
+                ])
+                .unwrap();
+                // Not execute() so that stopping doesn't shut down the other tests.
+                cli.run(&Shutdown::default()).unwrap_err()
+            })
+            .await
+            .unwrap()
+        })
+        .await
+        .expect("timed out");
 
-#[cfg(test)]
-mod tests {
-    use crate::cronthat::{CronThat, DATETIME_FORMAT};
-    use chrono::{Local, TimeDelta};
-    use clap::Parser;
-    use std::fs::File;
-    use std::io;
-    use std::ops::Add;
-    use tokio::task::spawn_blocking;
-    use tokio::time::timeout;
+        assert_eq!(err.to_string(), "memory usage exceeded --max-memory");
+    }
 
-    static CRON_EVERY_S: &'static str = "* * * * * *";
+    #[tokio::test]
+    async fn cronthat_execute_timeout_total() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let start = std::time::Instant::now();
 
-    #[test]
-    fn cronthat_parse_command() {
-        let cli =
-            CronThat::try_parse_from(vec!["cronthat", CRON_EVERY_S, "--", "echo", "hello-world"])
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            spawn_blocking(move || {
+                let cli = CronThat::try_parse_from(vec![
+                    "cronthat",
+                    CRON_EVERY_S,
+                    "--timeout-total",
+                    "1.5s",
+                    "--",
+                    &format!("echo helloworld >> {:?}; sleep 5", tmp_path),
+                ])
                 .unwrap();
-        assert_eq!(cli.command, vec!["echo", "hello-world"]);
+                cli.execute().unwrap();
+            })
+            .await
+            .unwrap();
+        })
+        .await
+        .expect("timed out");
+
+        assert!(start.elapsed() < std::time::Duration::from_secs(3));
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\n");
     }
 
     #[tokio::test]
-    async fn cronthat_execute_limited_repetitions() {
+    async fn cronthat_execute_stop_if() {
         let tmp = tempfile::NamedTempFile::new().unwrap();
         let tmp_path = tmp.path().to_path_buf();
 
-        let timeout_duration = tokio::time::Duration::from_secs(2);
+        let timeout_duration = tokio::time::Duration::from_secs(4);
         timeout(timeout_duration, async {
             let tmp_path = tmp_path.clone();
             spawn_blocking(move || {
-                let tmp_path = tmp_path.clone();
                 let cli = CronThat::try_parse_from(vec![
                     "cronthat",
                     CRON_EVERY_S,
-                    "--repetitions",
-                    "2",
+                    "--stop-if",
+                    &format!("test $(wc -l < {:?}) -ge 2", tmp_path),
                     "--",
                     &format!("echo helloworld >> {:?}", tmp_path),
                 ])
@@ -174,24 +5777,20 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn cronthat_execute_until() {
+    async fn cronthat_execute_continue_if() {
         let tmp = tempfile::NamedTempFile::new().unwrap();
         let tmp_path = tmp.path().to_path_buf();
 
-        let now = Local::now();
-        let in_three_seconds = now.add(TimeDelta::seconds(3));
-        let until = in_three_seconds.format(DATETIME_FORMAT).to_string();
-
-        let timeout_duration = tokio::time::Duration::from_secs(3);
+        let timeout_duration = tokio::time::Duration::from_secs(4);
         timeout(timeout_duration, async {
             let tmp_path = tmp_path.clone();
             spawn_blocking(move || {
-                let tmp_path = tmp_path.clone();
                 let cli = CronThat::try_parse_from(vec![
                     "cronthat",
                     CRON_EVERY_S,
-                    "--until",
-                    until.as_str(),
+                    "--continue-if",
+                    &format!("test $(wc -l < {:?}) -lt 2", tmp_path),
+                    "--pre-validate-guards",
                     "--",
                     &format!("echo helloworld >> {:?}", tmp_path),
                 ])
@@ -205,24 +5804,40 @@ mod tests {
         .expect("timed out");
 
         let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
-        assert_eq!(content, "helloworld\nhelloworld\nhelloworld\n");
+        assert_eq!(content, "helloworld\nhelloworld\n");
+
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--stop-if",
+            "cronthat-no-such-command",
+            "--pre-validate-guards",
+            "true",
+        ])
+        .unwrap();
+        cli.execute().expect_err("guard cannot be run");
     }
 
     #[tokio::test]
-    async fn cronthat_execute_error() {
-        let timeout_duration = tokio::time::Duration::from_secs(2);
+    async fn cronthat_execute_stop_file() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+        let stop_dir = tempfile::tempdir().unwrap();
+        let stop_path = stop_dir.path().join("stop");
 
-        // Default to ignore errors
-        timeout(timeout_duration.clone(), async {
+        let timeout_duration = tokio::time::Duration::from_secs(4);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
+            let stop_path = stop_path.clone();
             spawn_blocking(move || {
                 let cli = CronThat::try_parse_from(vec![
                     "cronthat",
                     CRON_EVERY_S,
-                    "--repetitions",
-                    "2",
+                    "--stop-file",
+                    stop_path.to_str().unwrap(),
+                    "--remove-stop-file",
                     "--",
-                    "exit",
-                    "1",
+                    &format!("echo helloworld >> {:?}; touch {:?}", tmp_path, stop_path),
                 ])
                 .unwrap();
                 cli.execute().unwrap();
@@ -233,47 +5848,64 @@ mod tests {
         .await
         .expect("timed out");
 
-        // Stop on errors
-        timeout(timeout_duration.clone(), async {
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content, "helloworld\n");
+        assert!(!stop_path.exists());
+    }
+
+    #[tokio::test]
+    async fn cronthat_execute_retry_max_elapsed() {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let tmp_path = tmp.path().to_path_buf();
+
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
+            let tmp_path = tmp_path.clone();
             spawn_blocking(move || {
                 let cli = CronThat::try_parse_from(vec![
                     "cronthat",
                     CRON_EVERY_S,
-                    "--stop-on-error",
-                    "--",
-                    "exit",
+                    "--repetitions",
                     "1",
+                    "--retry",
+                    "10",
+                    "--retry-max-elapsed",
+                    "500ms",
+                    "--",
+                    &format!("echo attempt >> {:?}; sleep 0.2; exit 1", tmp_path),
                 ])
                 .unwrap();
-                cli.execute().expect_err("must stop on error");
+                cli.execute().unwrap();
             })
             .await
             .unwrap();
         })
         .await
         .expect("timed out");
+
+        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
+        assert_eq!(content.lines().count(), 3);
     }
 
     #[tokio::test]
-    async fn cronthat_execute_now() {
-        let tmp = tempfile::NamedTempFile::new().unwrap();
-        let tmp_path = tmp.path().to_path_buf();
-
-        let timeout_duration = tokio::time::Duration::from_millis(1500);
-        let in_one_second = Local::now().add(TimeDelta::seconds(1));
+    async fn cronthat_execute_startup_delay() {
+        let dir = tempfile::tempdir().unwrap();
+        let command = timed_command(dir.path());
+        let start = Local::now();
 
-        // Default to ignore errors
-        timeout(timeout_duration.clone(), async {
-            let tmp_path = tmp_path.clone();
+        let timeout_duration = tokio::time::Duration::from_secs(3);
+        timeout(timeout_duration, async {
             spawn_blocking(move || {
                 let cli = CronThat::try_parse_from(vec![
                     "cronthat",
                     CRON_EVERY_S,
                     "--now",
-                    "--until",
-                    &in_one_second.format(DATETIME_FORMAT).to_string(),
+                    "--repetitions",
+                    "1",
+                    "--startup-delay",
+                    "1s",
                     "--",
-                    &format!("echo helloworld >> {:?}", tmp_path),
+                    &command,
                 ])
                 .unwrap();
                 cli.execute().unwrap();
@@ -284,7 +5916,113 @@ mod tests {
         .await
         .expect("timed out");
 
-        let content = io::read_to_string(File::open(tmp_path).unwrap()).unwrap();
-        assert_eq!(content, "helloworld\nhelloworld\n");
+        let runs = run_times(dir.path());
+        assert_eq!(runs.len(), 1);
+        let run = runs[0];
+        assert!(
+            run - start.timestamp_millis() >= 950,
+            "run before the delay"
+        );
+    }
+
+    #[test]
+    fn cronthat_startup_splay_seeded() {
+        let splay = |seed: &str| {
+            let cli = CronThat::try_parse_from(vec![
+                "cronthat",
+                CRON_EVERY_S,
+                "--startup-splay",
+                "10m",
+                "--seed",
+                seed,
+                "--",
+                "echo",
+            ])
+            .unwrap();
+            cli.splay(cli.startup_splay.unwrap())
+        };
+        assert_eq!(splay("42"), splay("42"));
+        assert_ne!(splay("42"), splay("43"));
+        for seed in ["1", "2", "3"] {
+            let splay = splay(seed);
+            assert!(splay >= TimeDelta::zero() && splay <= TimeDelta::minutes(10));
+        }
+    }
+
+    #[test]
+    fn cronthat_schedule_from_now() {
+        let schedule = cron::Schedule::from_str("0 0 12 * * *").unwrap();
+        let noon = Local.with_ymd_and_hms(2025, 1, 2, 12, 0, 0).unwrap();
+        let first_fire_time = |args: &[&str], now| {
+            let cli = CronThat::try_parse_from(
+                ["cronthat", "0 0 12 * * *"]
+                    .iter()
+                    .chain(args)
+                    .chain(&["--", "echo"]),
+            )
+            .unwrap();
+            schedule.after(&cli.schedule_start(now)).next().unwrap()
+        };
+
+        for now in [noon, noon + TimeDelta::milliseconds(400)] {
+            assert_eq!(first_fire_time(&[], now), noon + TimeDelta::days(1));
+            assert_eq!(first_fire_time(&["--schedule-from-now"], now), noon);
+        }
+        assert_eq!(
+            first_fire_time(&["--schedule-from-now"], noon + TimeDelta::seconds(1)),
+            noon + TimeDelta::days(1)
+        );
+    }
+
+    #[test]
+    fn cronthat_dump_config_round_trip() {
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            CRON_EVERY_S,
+            "--repetitions",
+            "3",
+            "--retry",
+            "2",
+            "--retry-scope",
+            "global",
+            "--retry-max-elapsed",
+            "1m30s",
+            "--expect-json-field",
+            "status=ok",
+            "--fail-output-regex",
+            "ERROR|FATAL",
+            "--",
+            "echo",
+            "hello-world",
+        ])
+        .unwrap();
+        let dumped = cli.dump_config().unwrap();
+
+        let mut config = tempfile::NamedTempFile::new().unwrap();
+        write!(config, "{}", dumped).unwrap();
+        let cli = CronThat::try_parse_from(vec![
+            "cronthat",
+            "--config",
+            config.path().to_str().unwrap(),
+        ])
+        .unwrap();
+        assert_eq!(cli.dump_config().unwrap(), dumped);
+        assert!(dumped.contains(r#"retry_max_elapsed = "1m30s""#));
+
+        // The command line options override the ones of the jobs.
+        let cli = CronThat::try_parse_args(vec![
+            "cronthat",
+            "--config",
+            config.path().to_str().unwrap(),
+            "--repetitions",
+            "5",
+        ])
+        .unwrap();
+        let overridden = cli.dump_config().unwrap();
+        assert_eq!(
+            overridden,
+            dumped.replace("repetitions = 3", "repetitions = 5")
+        );
+        assert_ne!(overridden, dumped);
     }
 }
@@ -0,0 +1,77 @@
+use anyhow::Result;
+use std::path::Path;
+
+/// Which side of the fork we are on after `daemonize`.
+pub enum Fork {
+    /// The original process, the daemon is running in the background.
+    Parent,
+    /// The detached daemon process.
+    Daemon,
+}
+
+/// Detach the process from its terminal with a double fork, redirecting stdin to `/dev/null` and
+/// stdout and stderr to `log_file` (or `/dev/null`), then write the daemon's PID to `pid_file`.
+/// Unlike most daemons, it keeps the working directory rather than moving to `/`: relative paths
+/// in the command and the options are resolved from where cronthat was started.
+/// Must be called before any thread is started.
+#[cfg(unix)]
+pub fn daemonize(log_file: Option<&Path>, pid_file: Option<&Path>) -> Result<Fork> {
+    use anyhow::Context;
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+    use std::os::unix::io::AsRawFd;
+
+    // Opened before forking so that errors are reported to the user.
+    let log = match log_file {
+        Some(path) => OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("cannot open log file {:?}", path))?,
+        None => OpenOptions::new().write(true).open("/dev/null")?,
+    };
+    let null = File::open("/dev/null")?;
+
+    // SAFETY: no other thread is running yet so the child starts from a consistent state.
+    match unsafe { libc::fork() } {
+        -1 => return Err(std::io::Error::last_os_error()).context("cannot fork"),
+        0 => {}
+        child => {
+            // Reap the intermediate child, which exits as soon as the daemon is forked.
+            unsafe { libc::waitpid(child, std::ptr::null_mut(), 0) };
+            return Ok(Fork::Parent);
+        }
+    }
+
+    // Become the leader of a new session without a controlling terminal, then fork again so that
+    // the daemon can never acquire one. The intermediate child leaves with _exit so that it
+    // doesn't flush the stdio buffers inherited from the parent a second time.
+    unsafe {
+        if libc::setsid() == -1 {
+            libc::_exit(1);
+        }
+        match libc::fork() {
+            -1 => libc::_exit(1),
+            0 => {}
+            _ => libc::_exit(0),
+        }
+    }
+
+    if let Some(path) = pid_file {
+        let mut file =
+            File::create(path).with_context(|| format!("cannot write pid file {:?}", path))?;
+        writeln!(file, "{}", std::process::id())?;
+    }
+    // SAFETY: the file descriptors are valid for the duration of the calls.
+    unsafe {
+        libc::dup2(null.as_raw_fd(), 0);
+        libc::dup2(log.as_raw_fd(), 1);
+        libc::dup2(log.as_raw_fd(), 2);
+    }
+    Ok(Fork::Daemon)
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_log_file: Option<&Path>, _pid_file: Option<&Path>) -> Result<Fork> {
+    anyhow::bail!("--daemon is only supported on Unix")
+}
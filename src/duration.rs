@@ -0,0 +1,176 @@
+use anyhow::{bail, Context, Result};
+use chrono::Duration;
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+/// Format a duration in a compact human-friendly way, e.g. `1h2m3s`, `1.2s` or `250ms`.
+/// Sub-second precision is only kept for durations under a minute.
+pub fn format_duration(duration: Duration) -> String {
+    let millis = duration.num_milliseconds();
+    let sign = if millis < 0 { "-" } else { "" };
+    let millis = millis.unsigned_abs();
+
+    if millis == 0 {
+        return "0s".to_string();
+    }
+    if millis < 1_000 {
+        return format!("{}{}ms", sign, millis);
+    }
+    if millis < 60_000 {
+        let (seconds, rest) = (millis / 1_000, millis % 1_000);
+        if rest == 0 {
+            return format!("{}{}s", sign, seconds);
+        }
+        let fraction = format!("{:03}", rest);
+        return format!("{}{}.{}s", sign, seconds, fraction.trim_end_matches('0'));
+    }
+
+    let mut seconds = millis / 1_000;
+    let mut formatted = sign.to_string();
+    for (unit, size) in [("d", 86_400), ("h", 3_600), ("m", 60), ("s", 1)] {
+        let value = seconds / size;
+        seconds %= size;
+        if value > 0 {
+            formatted.push_str(&format!("{}{}", value, unit));
+        }
+    }
+    formatted
+}
+
+/// Longest duration accepted by `parse_duration`, about 100 years, so that adding it to a date
+/// cannot overflow.
+static MAX_DURATION_DAYS: i64 = 36_500;
+
+/// Parse a duration written like `format_duration` outputs it, e.g. `1h30m`, `1.5s` or `250ms`. A
+/// bare number is a number of seconds and a leading `-` makes the duration negative.
+pub fn parse_duration(value: &str) -> Result<Duration> {
+    let value = value.trim();
+    let (negative, mut rest) = match value.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, value),
+    };
+    if rest.is_empty() {
+        bail!("empty duration");
+    }
+    if let Ok(seconds) = rest.parse::<f64>() {
+        let duration = checked_millis(seconds * 1_000.0, value)?;
+        return Ok(if negative { -duration } else { duration });
+    }
+
+    let mut total = Duration::zero();
+    while !rest.is_empty() {
+        let number_len = rest
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .context(format!("missing unit in duration {:?}", value))?;
+        let number: f64 = rest[..number_len]
+            .parse()
+            .with_context(|| format!("invalid duration {:?}", value))?;
+        rest = &rest[number_len..];
+        let unit_len = rest
+            .find(|c: char| c.is_ascii_digit() || c == '.')
+            .unwrap_or(rest.len());
+        let millis = match &rest[..unit_len] {
+            "ms" => 1.0,
+            "s" => 1_000.0,
+            "m" => 60_000.0,
+            "h" => 3_600_000.0,
+            "d" => 86_400_000.0,
+            unit => bail!("unknown unit {:?} in duration {:?}", unit, value),
+        };
+        rest = &rest[unit_len..];
+        total = total
+            .checked_add(&checked_millis(number * millis, value)?)
+            .filter(|total| total.num_days() <= MAX_DURATION_DAYS)
+            .with_context(|| out_of_range(value))?;
+    }
+    Ok(if negative { -total } else { total })
+}
+
+/// `millis` as a duration, failing if it is not finite or longer than `MAX_DURATION_DAYS`.
+fn checked_millis(millis: f64, value: &str) -> Result<Duration> {
+    let max_millis = (MAX_DURATION_DAYS * 86_400_000) as f64;
+    if !millis.is_finite() || millis.abs() > max_millis {
+        bail!("{}", out_of_range(value));
+    }
+    Duration::try_milliseconds(millis.round() as i64).with_context(|| out_of_range(value))
+}
+
+fn out_of_range(value: &str) -> String {
+    format!(
+        "duration {:?} is out of range, at most {}d",
+        value, MAX_DURATION_DAYS
+    )
+}
+
+/// Deserialize an optional duration written as a string, e.g. `late_threshold = "10s"` in a config.
+pub fn deserialize_optional<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|value| parse_duration(&value).map_err(de::Error::custom))
+        .transpose()
+}
+
+/// Serialize an optional duration the way `deserialize_optional` reads it.
+pub fn serialize_optional<S>(duration: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match duration {
+        Some(duration) => serializer.serialize_some(&format_duration(*duration)),
+        None => serializer.serialize_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::duration::{format_duration, parse_duration};
+    use chrono::Duration;
+
+    #[test]
+    fn format_duration_sub_second() {
+        assert_eq!(format_duration(Duration::zero()), "0s");
+        assert_eq!(format_duration(Duration::milliseconds(250)), "250ms");
+    }
+
+    #[test]
+    fn format_duration_seconds() {
+        assert_eq!(format_duration(Duration::seconds(5)), "5s");
+        assert_eq!(format_duration(Duration::milliseconds(1_200)), "1.2s");
+        assert_eq!(format_duration(Duration::milliseconds(-1_250)), "-1.25s");
+    }
+
+    #[test]
+    fn format_duration_long() {
+        assert_eq!(format_duration(Duration::seconds(3_723)), "1h2m3s");
+        assert_eq!(format_duration(Duration::hours(1)), "1h");
+        assert_eq!(format_duration(Duration::seconds(90_061)), "1d1h1m1s");
+        assert_eq!(format_duration(Duration::days(400)), "400d");
+    }
+
+    #[test]
+    fn parse_duration_units() {
+        assert_eq!(parse_duration("10").unwrap(), Duration::seconds(10));
+        assert_eq!(
+            parse_duration("250ms").unwrap(),
+            Duration::milliseconds(250)
+        );
+        assert_eq!(
+            parse_duration("1.5s").unwrap(),
+            Duration::milliseconds(1_500)
+        );
+        assert_eq!(parse_duration("1h2m3s").unwrap(), Duration::seconds(3_723));
+        assert_eq!(parse_duration("-5m").unwrap(), Duration::minutes(-5));
+        parse_duration("5 parsecs").expect_err("unknown unit");
+        parse_duration("").expect_err("empty duration");
+
+        assert_eq!(parse_duration("36500d").unwrap(), Duration::days(36_500));
+        for value in ["inf", "-inf", "NaN", "1e30", "100000000d", "36000d1000d"] {
+            let err = parse_duration(value).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("duration {:?} is out of range, at most 36500d", value)
+            );
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Local, TimeZone};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+pub static HASH_PLACEHOLDER: &str = "{hash}";
+
+/// Lock file held for the whole life of the process, removed when dropped. On Unix it holds an
+/// exclusive `flock` like [PidLock], so a lock file left behind by a crash or a reboot is taken
+/// over instead of blocking every later start.
+pub struct LockFile {
+    path: PathBuf,
+    _file: File,
+}
+
+impl LockFile {
+    #[cfg(unix)]
+    pub fn acquire(path: &Path) -> Result<LockFile> {
+        let Some(file) = lock_with_pid(path)? else {
+            bail!("lock file {:?} is held by another instance", path)
+        };
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn acquire(path: &Path) -> Result<LockFile> {
+        let mut file = match create_new(path) {
+            Ok(file) => file,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                bail!("lock file {:?} is held by another instance", path)
+            }
+            Err(err) => return Err(err).with_context(|| format!("cannot create {:?}", path)),
+        };
+        writeln!(file, "{}", std::process::id())?;
+        Ok(LockFile {
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+}
+
+impl Drop for LockFile {
+    fn drop(&mut self) {
+        // See `PidLock`'s drop.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Lock file holding the PID of its owner and an exclusive `flock` for the whole life of the
+/// process, removed when dropped. The `flock` alone tells whether the lock is held: it is
+/// released when its owner dies, so a lock file left behind by a crash is simply taken over.
+pub struct PidLock {
+    path: PathBuf,
+    _file: File,
+}
+
+impl PidLock {
+    #[cfg(unix)]
+    pub fn acquire(path: &Path) -> Result<PidLock> {
+        let Some(file) = lock_with_pid(path)? else {
+            // The PID may be another host's, it is only reported.
+            match fs::read_to_string(path).map(|content| content.trim().parse::<u32>()) {
+                Ok(Ok(pid)) => bail!("pid lock {:?} is held by process {}", path, pid),
+                _ => bail!("pid lock {:?} is held by another instance", path),
+            }
+        };
+        Ok(PidLock {
+            path: path.to_path_buf(),
+            _file: file,
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn acquire(_path: &Path) -> Result<PidLock> {
+        bail!("--pid-lock is only supported on Unix")
+    }
+}
+
+impl Drop for PidLock {
+    fn drop(&mut self) {
+        // Safe while the lock is held, `lock_exclusive` checks that the locked file is still
+        // the one at the path.
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Take the lock of `path` with `lock_exclusive` and replace its content with our PID. Returns
+/// `None` if another process holds it.
+#[cfg(unix)]
+fn lock_with_pid(path: &Path) -> Result<Option<File>> {
+    use std::io::Seek;
+
+    let Some(mut file) = lock_exclusive(path)? else {
+        return Ok(None);
+    };
+    file.set_len(0)?;
+    file.rewind()?;
+    writeln!(file, "{}", std::process::id())?;
+    Ok(Some(file))
+}
+
+/// Open `path`, creating it if needed, and take an exclusive `flock` on it. Returns `None` if
+/// another process holds it. Retries when the file was removed or replaced between opening and
+/// locking it, the lock would otherwise be on a stale inode.
+#[cfg(unix)]
+fn lock_exclusive(path: &Path) -> Result<Option<File>> {
+    use std::os::unix::fs::MetadataExt;
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)
+            .with_context(|| format!("cannot open {:?}", path))?;
+        // SAFETY: the file descriptor is valid while `file` is alive.
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+            let err = std::io::Error::last_os_error();
+            if err.kind() == ErrorKind::WouldBlock {
+                return Ok(None);
+            }
+            return Err(err).with_context(|| format!("cannot lock {:?}", path));
+        }
+        let locked = file.metadata()?;
+        match fs::metadata(path) {
+            Ok(current) if (current.dev(), current.ino()) == (locked.dev(), locked.ino()) => {
+                return Ok(Some(file));
+            }
+            Ok(_) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => return Err(err).with_context(|| format!("cannot stat {:?}", path)),
+        }
+    }
+}
+
+/// Claim the execution slot of `command` at `scheduled` by creating the lock file named after
+/// their hash, returns `false` if another instance already claimed it. Slot lock files are kept so
+/// that slower instances don't run the slot after the first one is done, they are never removed:
+/// clean the directory up from time to time, e.g. with `find -mtime`.
+pub fn claim_slot(path: &Path, command: &str, scheduled: &DateTime<Local>) -> Result<bool> {
+    let path = slot_path(path, command, scheduled);
+    match create_new(&path) {
+        Ok(mut file) => {
+            writeln!(file, "{}", std::process::id())?;
+            Ok(true)
+        }
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => Ok(false),
+        Err(err) => Err(err).with_context(|| format!("cannot create {:?}", path)),
+    }
+}
+
+/// Substitute `{hash}` in `path` with the SHA-256 of the command and its scheduled time, as a
+/// Unix timestamp so that replicas in other time zones claim the same slot.
+fn slot_path<Tz: TimeZone>(path: &Path, command: &str, scheduled: &DateTime<Tz>) -> PathBuf {
+    let hash = Sha256::digest(format!("{}\n{}", command, scheduled.timestamp()));
+    PathBuf::from(
+        path.to_string_lossy()
+            .replace(HASH_PLACEHOLDER, &format!("{:x}", hash)),
+    )
+}
+
+fn create_new(path: &Path) -> std::io::Result<File> {
+    OpenOptions::new().write(true).create_new(true).open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock::{slot_path, LockFile, PidLock};
+    use chrono::{FixedOffset, TimeZone, Utc};
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    #[test]
+    fn slot_path_ignores_time_zone() {
+        let path = Path::new("/locks/{hash}.lock");
+        let utc = Utc.with_ymd_and_hms(2025, 1, 2, 15, 4, 0).unwrap();
+        let paris = utc.with_timezone(&FixedOffset::east_opt(3600).unwrap());
+        assert_eq!(
+            slot_path(path, "true", &utc),
+            slot_path(path, "true", &paris)
+        );
+        assert_ne!(
+            slot_path(path, "true", &utc),
+            slot_path(path, "false", &utc)
+        );
+        assert!(slot_path(path, "true", &utc)
+            .to_str()
+            .unwrap()
+            .starts_with("/locks/"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_file_held_or_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cronthat.lock");
+
+        let lock = LockFile::acquire(&path).unwrap();
+        let err = LockFile::acquire(&path).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("lock file {:?} is held by another instance", path)
+        );
+        drop(lock);
+        assert!(!path.exists());
+
+        // Left behind by a process killed before removing it.
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        fs::write(&path, format!("{}\n", child.id())).unwrap();
+        let _lock = LockFile::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn pid_lock_held_or_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cronthat.lock");
+
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+        let err = PidLock::acquire(&path).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "pid lock {:?} is held by process {}",
+                path,
+                std::process::id()
+            )
+        );
+        drop(lock);
+        assert!(!path.exists());
+
+        // Left behind by a process that is not running anymore, its flock is gone.
+        let mut child = Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        fs::write(&path, format!("{}\n", child.id())).unwrap();
+        let lock = PidLock::acquire(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            format!("{}\n", std::process::id())
+        );
+
+        // Held, even though the PID is not running here, e.g. written by another host.
+        fs::write(&path, format!("{}\n", child.id())).unwrap();
+        let err = PidLock::acquire(&path).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            format!("pid lock {:?} is held by process {}", path, child.id())
+        );
+        drop(lock);
+        assert!(!path.exists());
+    }
+}
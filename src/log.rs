@@ -0,0 +1,60 @@
+use clap::ValueEnum;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Most verbose level of cronthat's own messages printed, the command's output is never
+/// filtered.
+#[derive(Clone, Copy, Debug, Default, PartialEq, PartialOrd, ValueEnum)]
+pub enum LogLevel {
+    /// Only errors
+    Error,
+    /// Errors and warnings
+    Warn,
+    /// Errors, warnings and the progress of the schedule
+    #[default]
+    Info,
+    /// Also the upcoming executions and the control requests
+    Debug,
+    /// Also every sleep
+    Trace,
+}
+
+static LEVEL: AtomicU8 = AtomicU8::new(LogLevel::Info as u8);
+
+/// Print the messages up to `level` from now on, for the whole process.
+pub fn set_level(level: LogLevel) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+pub fn enabled(level: LogLevel) -> bool {
+    level as u8 <= LEVEL.load(Ordering::Relaxed)
+}
+
+macro_rules! log {
+    ($level:ident, $($arg:tt)*) => {
+        if $crate::log::enabled($crate::log::LogLevel::$level) {
+            println!($($arg)*);
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log::log!(Error, $($arg)*) };
+}
+
+macro_rules! warning {
+    ($($arg:tt)*) => { $crate::log::log!(Warn, $($arg)*) };
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log::log!(Info, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log::log!(Debug, $($arg)*) };
+}
+
+macro_rules! trace {
+    ($($arg:tt)*) => { $crate::log::log!(Trace, $($arg)*) };
+}
+
+pub(crate) use {debug, error, info, log, trace, warning};
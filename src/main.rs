@@ -1,16 +1,37 @@
+mod budget;
+mod calendar;
+mod config;
+mod control;
 mod cronthat;
+mod daemon;
+mod duration;
+mod lock;
+mod log;
+mod marker;
+mod output;
+mod pushgateway;
+mod schedule;
+mod secret;
+mod shutdown;
+mod state;
+mod status;
+mod tag;
+mod tee;
+mod timestamp;
+mod tui;
+mod usage;
 
 use crate::cronthat::CronThat;
+use crate::log::error;
 use anyhow::Context;
-use clap::Parser;
 
 fn main() {
-    let cli = CronThat::parse();
+    let cli = CronThat::try_parse_args(std::env::args_os()).unwrap_or_else(|err| err.exit());
     let res = cli.execute().context("Something went wrong");
     match res {
         Ok(_) => {}
         Err(err) => {
-            println!("{:?}", err);
+            error!("{:?}", err);
             std::process::exit(1);
         }
     }
@@ -0,0 +1,126 @@
+use anyhow::{bail, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Regular expression looked for in a file written by another process, written `PATH:PATTERN`.
+/// The path ends at the first `:`, so it cannot contain one while the pattern can, e.g.
+/// `/tmp/job.log:finished at \d+:\d+`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Marker {
+    pub path: PathBuf,
+    pub pattern: Regex,
+}
+
+impl Marker {
+    /// Look for the pattern in the file at `path` instead, which must not contain a `:` to be
+    /// written back as `PATH:PATTERN`.
+    pub fn set_path(&mut self, path: PathBuf) -> Result<()> {
+        if path.to_string_lossy().contains(':') {
+            bail!(
+                "path {:?} contains ':', which separates it from the pattern",
+                path
+            );
+        }
+        self.path = path;
+        Ok(())
+    }
+
+    /// Whether the file contains the pattern, `false` while it doesn't exist.
+    pub fn found(&self) -> Result<bool> {
+        match fs::read_to_string(&self.path) {
+            Ok(content) => Ok(self.pattern.is_match(&content)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err).with_context(|| format!("cannot read {:?}", self.path)),
+        }
+    }
+}
+
+impl FromStr for Marker {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Marker> {
+        let (path, pattern) = value
+            .split_once(':')
+            .context("expected a file and a pattern formatted as PATH:PATTERN")?;
+        if path.is_empty() {
+            bail!("empty path before pattern {:?}", pattern);
+        }
+        Ok(Marker {
+            path: PathBuf::from(path),
+            pattern: Regex::new(pattern)
+                .with_context(|| format!("invalid pattern {:?}", pattern))?,
+        })
+    }
+}
+
+impl TryFrom<String> for Marker {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Marker> {
+        value.parse()
+    }
+}
+
+impl From<Marker> for String {
+    fn from(marker: Marker) -> String {
+        format!("{}:{}", marker.path.display(), marker.pattern)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::marker::Marker;
+    use std::fs;
+
+    #[test]
+    fn marker_found() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pipeline.log");
+        let marker: Marker = format!("{}:^DONE( |$)", path.display()).parse().unwrap();
+        assert!(!marker.found().unwrap());
+
+        fs::write(&path, "STARTED\nDONEISH\n").unwrap();
+        assert!(!marker.found().unwrap());
+        fs::write(&path, "STARTED\nDONE\n").unwrap();
+        assert!(
+            !marker.found().unwrap(),
+            "^ only matches the start of the file"
+        );
+
+        let marker: Marker = format!("{}:(?m)^DONE$", path.display()).parse().unwrap();
+        assert!(marker.found().unwrap());
+
+        // Only the first ':' separates the path from the pattern.
+        let mut marker: Marker = format!("{}:finished at \\d+:\\d+ UTC", path.display())
+            .parse()
+            .unwrap();
+        assert_eq!(marker.path, path);
+        assert_eq!(marker.pattern.as_str(), r"finished at \d+:\d+ UTC");
+        fs::write(&path, "finished at 12:30 UTC\n").unwrap();
+        assert!(marker.found().unwrap());
+        assert_eq!(
+            String::from(marker.clone())
+                .parse::<Marker>()
+                .unwrap()
+                .pattern
+                .as_str(),
+            marker.pattern.as_str()
+        );
+        marker
+            .set_path(dir.path().join("a:b.log"))
+            .expect_err("':' in the path");
+
+        "pipeline.log"
+            .parse::<Marker>()
+            .expect_err("missing pattern");
+        ":DONE".parse::<Marker>().expect_err("empty path");
+        "pipeline.log:DONE("
+            .parse::<Marker>()
+            .expect_err("invalid pattern");
+    }
+}
@@ -0,0 +1,673 @@
+use crate::log::{info, warning};
+use crate::shutdown::Shutdown;
+use anyhow::{bail, Context, Result};
+use base64::prelude::{Engine, BASE64_STANDARD};
+use clap::ValueEnum;
+use encoding_rs::{Decoder, Encoding};
+use regex::Regex;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::Value;
+use std::borrow::Cow;
+use std::io;
+use std::io::{Read, Write};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Duration;
+
+/// How often `forward` checks the shutdown while the command doesn't write anything.
+static SHUTDOWN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How captured output is forwarded to cronthat's own output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FlushMode {
+    /// Forward each complete line as soon as it is read
+    #[default]
+    Line,
+    /// Forward the whole output once the command is done
+    Block,
+    /// Forward the output as soon as it is read
+    None,
+}
+
+/// Forward everything read from `reader` to `writer` according to `mode` and return it. Once
+/// `shutdown` is triggered, the output held back by `mode` is forwarded right away so that it
+/// isn't lost if cronthat is killed before the command is done.
+pub fn forward(
+    reader: impl Read + Send + 'static,
+    mut writer: impl Write,
+    mut mode: FlushMode,
+    shutdown: &Shutdown,
+) -> io::Result<Vec<u8>> {
+    // Read from another thread so that the shutdown is noticed while the command is silent.
+    let (sender, chunks) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = reader;
+        let mut buffer = [0; 8192];
+        loop {
+            let chunk = match reader.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => Ok(buffer[..read].to_vec()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => Err(err),
+            };
+            let failed = chunk.is_err();
+            if sender.send(chunk).is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    let mut captured = Vec::new();
+    let mut pending = 0;
+    let mut result = Ok(());
+    loop {
+        match chunks.recv_timeout(SHUTDOWN_POLL_INTERVAL) {
+            Ok(Ok(chunk)) => captured.extend_from_slice(&chunk),
+            Ok(Err(err)) => {
+                result = Err(err);
+                break;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => break,
+        }
+        if shutdown.is_triggered() {
+            mode = FlushMode::None;
+        }
+        let flushable = match mode {
+            FlushMode::Line => captured[pending..]
+                .iter()
+                .rposition(|&byte| byte == b'\n')
+                .map_or(pending, |newline| pending + newline + 1),
+            FlushMode::Block => pending,
+            FlushMode::None => captured.len(),
+        };
+        if flushable > pending {
+            writer.write_all(&captured[pending..flushable])?;
+            writer.flush()?;
+            pending = flushable;
+        }
+    }
+    if captured.len() > pending {
+        writer.write_all(&captured[pending..])?;
+        writer.flush()?;
+    }
+    result.map(|_| captured)
+}
+
+/// Character encoding of the command's output, given as any label of the WHATWG Encoding
+/// Standard (e.g. `latin1`, `shift_jis`).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct OutputEncoding(&'static Encoding);
+
+impl FromStr for OutputEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<OutputEncoding> {
+        Encoding::for_label(value.as_bytes())
+            .map(OutputEncoding)
+            .with_context(|| format!("unknown encoding {:?}", value))
+    }
+}
+
+impl TryFrom<String> for OutputEncoding {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<OutputEncoding> {
+        value.parse()
+    }
+}
+
+impl From<OutputEncoding> for String {
+    fn from(encoding: OutputEncoding) -> String {
+        encoding.0.name().to_string()
+    }
+}
+
+/// Whether a `Transcode` already warned about invalid input, shared by all the executions like
+/// `INVALID_UTF8_WARNED`.
+static INVALID_ENCODING_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Reader decoding `inner` from an `OutputEncoding` to UTF-8, replacing invalid sequences with
+/// U+FFFD and warning about it once per process.
+pub struct Transcode<R> {
+    inner: R,
+    encoding: &'static Encoding,
+    decoder: Decoder,
+    decoded: Vec<u8>,
+    done: bool,
+}
+
+impl<R: Read> Transcode<R> {
+    pub fn new(inner: R, encoding: OutputEncoding) -> Transcode<R> {
+        Transcode {
+            inner,
+            encoding: encoding.0,
+            decoder: encoding.0.new_decoder(),
+            decoded: Vec::new(),
+            done: false,
+        }
+    }
+}
+
+impl<R: Read> Read for Transcode<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.decoded.is_empty() && !self.done {
+            let mut chunk = [0; 8192];
+            let read = self.inner.read(&mut chunk)?;
+            self.done = read == 0;
+            // Sequences split across reads are kept by the decoder until the next one.
+            let capacity = self
+                .decoder
+                .max_utf8_buffer_length(read)
+                .unwrap_or(read * 3);
+            let mut decoded = String::with_capacity(capacity);
+            let (_, _, replaced) =
+                self.decoder
+                    .decode_to_string(&chunk[..read], &mut decoded, self.done);
+            if replaced && !INVALID_ENCODING_WARNED.swap(true, Ordering::Relaxed) {
+                warning!(
+                    "warning: command output is not valid {}, invalid bytes are replaced",
+                    self.encoding.name()
+                );
+            }
+            self.decoded = decoded.into_bytes();
+        }
+        let read = buf.len().min(self.decoded.len());
+        buf[..read].copy_from_slice(&self.decoded[..read]);
+        self.decoded.drain(..read);
+        Ok(read)
+    }
+}
+
+/// Whether `decode` already warned about invalid UTF-8, so that a command running every second
+/// doesn't flood the log.
+static INVALID_UTF8_WARNED: AtomicBool = AtomicBool::new(false);
+
+/// Convert captured output to text for the text-based checks, replacing invalid UTF-8 sequences
+/// with U+FFFD (the forwarded output keeps the raw bytes). Warns about it once per process.
+pub fn decode(captured: &[u8]) -> String {
+    match String::from_utf8_lossy(captured) {
+        Cow::Borrowed(output) => output.to_string(),
+        Cow::Owned(output) => {
+            if !INVALID_UTF8_WARNED.swap(true, Ordering::Relaxed) {
+                warning!(
+                    "warning: command output is not valid UTF-8, invalid bytes are replaced for \
+                     the output checks"
+                );
+            }
+            output
+        }
+    }
+}
+
+/// One line printed by --json-runs, the output is base64-encoded so that any bytes survive.
+#[derive(Serialize)]
+pub struct RunRecord {
+    pub exit_code: i32,
+    pub duration_ms: i64,
+    pub stdout: String,
+    pub stderr: String,
+    /// Command as run, with --echo-command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+}
+
+impl RunRecord {
+    pub fn new(exit_code: i32, duration: chrono::Duration, stdout: &[u8], stderr: &[u8]) -> Self {
+        RunRecord {
+            exit_code,
+            duration_ms: duration.num_milliseconds(),
+            stdout: BASE64_STANDARD.encode(stdout),
+            stderr: BASE64_STANDARD.encode(stderr),
+            command: None,
+        }
+    }
+}
+
+/// Last `lines` lines of `output`, ending with a newline unless empty.
+pub fn tail(output: &str, lines: usize) -> String {
+    let kept: Vec<&str> = output.lines().rev().take(lines).collect();
+    kept.iter()
+        .rev()
+        .map(|line| format!("{}\n", line))
+        .collect()
+}
+
+/// Writer starting every line with `prefix` followed by a space, passing everything through
+/// without one.
+pub struct LinePrefix<W> {
+    inner: W,
+    prefix: Option<String>,
+    line_start: bool,
+}
+
+impl<W: Write> LinePrefix<W> {
+    pub fn new(inner: W, prefix: Option<String>) -> LinePrefix<W> {
+        LinePrefix {
+            inner,
+            prefix,
+            line_start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for LinePrefix<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(prefix) = &self.prefix else {
+            return self.inner.write(buf);
+        };
+        for line in buf.split_inclusive(|&byte| byte == b'\n') {
+            if self.line_start {
+                write!(self.inner, "{} ", prefix)?;
+            }
+            self.inner.write_all(line)?;
+            self.line_start = line.ends_with(b"\n");
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Writer passing through the first `max_lines` lines and replacing the rest with a single
+/// `... (truncated)` line.
+pub struct LineLimit<W> {
+    inner: W,
+    remaining: Option<usize>,
+    truncated: bool,
+}
+
+impl<W: Write> LineLimit<W> {
+    pub fn new(inner: W, max_lines: Option<usize>) -> LineLimit<W> {
+        LineLimit {
+            inner,
+            remaining: max_lines,
+            truncated: false,
+        }
+    }
+}
+
+impl<W: Write> Write for LineLimit<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let Some(remaining) = self.remaining.as_mut() else {
+            return self.inner.write(buf);
+        };
+        let mut kept = buf.len();
+        for (i, &byte) in buf.iter().enumerate() {
+            if *remaining == 0 {
+                kept = i;
+                break;
+            }
+            if byte == b'\n' {
+                *remaining -= 1;
+            }
+        }
+        self.inner.write_all(&buf[..kept])?;
+        if kept < buf.len() && !self.truncated {
+            self.inner.write_all(b"... (truncated)\n")?;
+            self.truncated = true;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Expected value of a field of the command's JSON output, written `path=value` where nested
+/// fields are separated by dots (e.g. `checks.db=ok`).
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct JsonField {
+    path: String,
+    expected: String,
+}
+
+impl FromStr for JsonField {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<JsonField> {
+        let (path, expected) = value
+            .split_once('=')
+            .context("expected a field formatted as PATH=VALUE")?;
+        if path.is_empty() {
+            bail!("empty field path in {:?}", value);
+        }
+        Ok(JsonField {
+            path: path.to_string(),
+            expected: expected.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for JsonField {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<JsonField> {
+        value.parse()
+    }
+}
+
+impl From<JsonField> for String {
+    fn from(field: JsonField) -> String {
+        format!("{}={}", field.path, field.expected)
+    }
+}
+
+/// Parse the command's output as JSON and check that every field has its expected value, strings
+/// are compared without their quotes and other values with their JSON representation.
+pub fn check_json(output: &str, fields: &[JsonField]) -> Result<()> {
+    let json: Value = serde_json::from_str(output).context("command output is not valid JSON")?;
+    for field in fields {
+        let pointer = format!("/{}", field.path.replace('.', "/"));
+        let actual = match json.pointer(&pointer) {
+            Some(Value::String(value)) => value.clone(),
+            Some(value) => value.to_string(),
+            None => bail!("JSON field {} is missing", field.path),
+        };
+        if actual != field.expected {
+            bail!(
+                "JSON field {} is {:?}, expected {:?}",
+                field.path,
+                actual,
+                field.expected
+            );
+        }
+        info!("JSON field {} matched {:?}", field.path, actual);
+    }
+    Ok(())
+}
+
+/// Check that the output matches every `expected` regex and none of the `forbidden` ones.
+pub fn check_regexes(output: &str, expected: &[Regex], forbidden: &[Regex]) -> Result<()> {
+    for regex in expected {
+        if !regex.is_match(output) {
+            bail!("command output does not match {:?}", regex.as_str());
+        }
+    }
+    for regex in forbidden {
+        if regex.is_match(output) {
+            bail!("command output matches {:?}", regex.as_str());
+        }
+    }
+    Ok(())
+}
+
+/// Deserialize a list of regexes written as strings in a config.
+pub fn deserialize_regexes<'de, D>(deserializer: D) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(de::Error::custom))
+        .collect()
+}
+
+/// Serialize a list of regexes as their patterns.
+pub fn serialize_regexes<S>(regexes: &[Regex], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.collect_seq(regexes.iter().map(Regex::as_str))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{
+        check_json, check_regexes, decode, forward, tail, FlushMode, JsonField, LineLimit,
+        LinePrefix, OutputEncoding, Transcode,
+    };
+    use crate::shutdown::Shutdown;
+    use regex::Regex;
+    use std::io;
+    use std::io::{Read, Write};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// Reader returning one chunk per read.
+    struct Chunks(Vec<&'static str>);
+
+    impl Read for Chunks {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.0.is_empty() {
+                return Ok(0);
+            }
+            let chunk = self.0.remove(0).as_bytes();
+            buf[..chunk.len()].copy_from_slice(chunk);
+            Ok(chunk.len())
+        }
+    }
+
+    /// Writer recording each write separately.
+    #[derive(Default)]
+    struct Writes(Vec<String>);
+
+    impl Write for Writes {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(String::from_utf8_lossy(buf).to_string());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn forward_flush_modes() {
+        for (mode, expected) in [
+            (FlushMode::Line, vec!["a\n", "bc\n", "d"]),
+            (FlushMode::Block, vec!["a\nbc\nd"]),
+            (FlushMode::None, vec!["a\nb", "c\n", "d"]),
+        ] {
+            let mut writes = Writes::default();
+            let captured = forward(
+                Chunks(vec!["a\nb", "c\n", "d"]),
+                &mut writes,
+                mode,
+                &Shutdown::default(),
+            )
+            .unwrap();
+            assert_eq!(captured, b"a\nbc\nd");
+            assert_eq!(writes.0, expected, "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn forward_flushes_on_shutdown() {
+        let writes = Arc::new(Mutex::new(Writes::default()));
+
+        /// Command writing a partial block then staying silent until it is forwarded.
+        struct Silent(Option<&'static str>, Arc<Mutex<Writes>>);
+
+        impl Read for Silent {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                if let Some(chunk) = self.0.take() {
+                    buf[..chunk.len()].copy_from_slice(chunk.as_bytes());
+                    return Ok(chunk.len());
+                }
+                let deadline = Instant::now() + Duration::from_secs(2);
+                while self.1.lock().unwrap().0.is_empty() && Instant::now() < deadline {
+                    std::thread::sleep(Duration::from_millis(10));
+                }
+                Ok(0)
+            }
+        }
+
+        struct Shared(Arc<Mutex<Writes>>);
+
+        impl Write for Shared {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.0.lock().unwrap().write(buf)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let shutdown = Shutdown::default();
+        let trigger = shutdown.clone();
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(200));
+            trigger.trigger();
+        });
+        let start = Instant::now();
+        let captured = forward(
+            Silent(Some("partial"), writes.clone()),
+            Shared(writes.clone()),
+            FlushMode::Block,
+            &shutdown,
+        )
+        .unwrap();
+        assert_eq!(captured, b"partial");
+        assert_eq!(writes.lock().unwrap().0, vec!["partial"]);
+        assert!(start.elapsed() < Duration::from_secs(1), "flushed at exit");
+    }
+
+    #[test]
+    fn decode_invalid_utf8() {
+        assert_eq!(decode("café\n".as_bytes()), "café\n");
+        assert_eq!(decode(b"caf\xe9\n"), "caf\u{fffd}\n");
+    }
+
+    #[test]
+    fn transcode_output() {
+        let latin1: OutputEncoding = "latin1".parse().unwrap();
+        let mut decoded = String::new();
+        Transcode::new(&b"caf\xe9\n"[..], latin1)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "café\n");
+
+        /// Reader returning one byte per read, to split multi-byte sequences.
+        struct Bytes(&'static [u8]);
+
+        impl Read for Bytes {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                let Some((&byte, rest)) = self.0.split_first() else {
+                    return Ok(0);
+                };
+                buf[0] = byte;
+                self.0 = rest;
+                Ok(1)
+            }
+        }
+
+        let shift_jis: OutputEncoding = "shift_jis".parse().unwrap();
+        let mut decoded = String::new();
+        Transcode::new(Bytes(b"\x82\xa0\x82\xa2 \x82"), shift_jis)
+            .read_to_string(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, "あい \u{fffd}");
+
+        "klingon"
+            .parse::<OutputEncoding>()
+            .expect_err("unknown encoding");
+        assert_eq!(String::from(latin1), "windows-1252");
+    }
+
+    #[test]
+    fn forward_line_limit() {
+        let chunks = || Chunks(vec!["a\nb", "c\nd\n", "e\n"]);
+        let mut writes = Writes::default();
+        let captured = forward(
+            chunks(),
+            LineLimit::new(&mut writes, Some(2)),
+            FlushMode::None,
+            &Shutdown::default(),
+        )
+        .unwrap();
+        assert_eq!(captured, b"a\nbc\nd\ne\n");
+        assert_eq!(writes.0.concat(), "a\nbc\n... (truncated)\n");
+
+        let mut writes = Writes::default();
+        forward(
+            chunks(),
+            LineLimit::new(&mut writes, Some(3)),
+            FlushMode::Line,
+            &Shutdown::default(),
+        )
+        .unwrap();
+        assert_eq!(writes.0.concat(), "a\nbc\nd\n... (truncated)\n");
+
+        let mut writes = Writes::default();
+        forward(
+            chunks(),
+            LineLimit::new(&mut writes, None),
+            FlushMode::Line,
+            &Shutdown::default(),
+        )
+        .unwrap();
+        assert_eq!(writes.0.concat(), "a\nbc\nd\ne\n");
+    }
+
+    #[test]
+    fn forward_line_prefix() {
+        let mut writes = Writes::default();
+        forward(
+            Chunks(vec!["a\nb", "c\n", "d"]),
+            LinePrefix::new(&mut writes, Some("abc123".to_string())),
+            FlushMode::None,
+            &Shutdown::default(),
+        )
+        .unwrap();
+        assert_eq!(writes.0.concat(), "abc123 a\nabc123 bc\nabc123 d");
+
+        let mut writes = Writes::default();
+        forward(
+            Chunks(vec!["a\nb"]),
+            LinePrefix::new(&mut writes, None),
+            FlushMode::None,
+            &Shutdown::default(),
+        )
+        .unwrap();
+        assert_eq!(writes.0.concat(), "a\nb");
+    }
+
+    #[test]
+    fn tail_last_lines() {
+        assert_eq!(tail("1\n2\n3\n4\n", 2), "3\n4\n");
+        assert_eq!(tail("1\n2", 5), "1\n2\n");
+        assert_eq!(tail("", 3), "");
+    }
+
+    #[test]
+    fn check_json_fields() {
+        let output = r#"{"status": "ok", "checks": {"db": true, "latency": 12}}"#;
+        let fields: Vec<JsonField> = vec![
+            "status=ok".parse().unwrap(),
+            "checks.db=true".parse().unwrap(),
+            "checks.latency=12".parse().unwrap(),
+        ];
+        check_json(output, &fields).unwrap();
+
+        check_json(output, &["status=ko".parse().unwrap()]).expect_err("mismatch");
+        check_json(output, &["missing=ok".parse().unwrap()]).expect_err("missing field");
+        check_json("not json", &[]).expect_err("invalid JSON");
+        "no-separator"
+            .parse::<JsonField>()
+            .expect_err("invalid field");
+    }
+
+    #[test]
+    fn check_output_regexes() {
+        let output = "processed 12 items\nWARNING: slow disk\n";
+        let expected = [Regex::new(r"processed \d+ items").unwrap()];
+        let forbidden = [Regex::new("ERROR").unwrap()];
+        check_regexes(output, &expected, &forbidden).unwrap();
+
+        let forbidden = [Regex::new("ERROR|WARNING").unwrap()];
+        check_regexes(output, &expected, &forbidden).expect_err("forbidden match");
+        let expected = [Regex::new("done").unwrap()];
+        check_regexes(output, &expected, &[]).expect_err("expected no match");
+    }
+}
@@ -0,0 +1,165 @@
+use crate::tag::Tag;
+use anyhow::{bail, Context, Result};
+use chrono::{DateTime, Duration, Local};
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpStream;
+
+static TIMEOUT: std::time::Duration = std::time::Duration::from_secs(5);
+
+/// Metrics of the last execution pushed to the Pushgateway.
+pub struct Metrics {
+    pub exit_code: i32,
+    pub duration: Duration,
+    pub last_success: Option<DateTime<Local>>,
+}
+
+impl Metrics {
+    /// Render the metrics in the Prometheus text format.
+    fn to_text(&self) -> String {
+        let mut text = String::new();
+        let mut gauge = |name: &str, value: String| {
+            text.push_str(&format!("# TYPE {} gauge\n{} {}\n", name, name, value));
+        };
+        gauge("cronthat_last_exit_code", self.exit_code.to_string());
+        gauge(
+            "cronthat_last_duration_seconds",
+            format!("{:.3}", self.duration.num_milliseconds() as f64 / 1_000.0),
+        );
+        if let Some(last_success) = self.last_success {
+            gauge(
+                "cronthat_last_success_timestamp_seconds",
+                last_success.timestamp().to_string(),
+            );
+        }
+        text
+    }
+}
+
+/// Replace the metrics of `job` grouped by `tags` on the Pushgateway at `url` (only plain
+/// `http://` is supported).
+pub fn push(url: &str, job: &str, tags: &[Tag], metrics: &Metrics) -> Result<()> {
+    let rest = url
+        .strip_prefix("http://")
+        .with_context(|| format!("unsupported Pushgateway URL {:?}, expected http://", url))?;
+    let (host, base) = rest.split_once('/').unwrap_or((rest, ""));
+    let base = base.trim_end_matches('/');
+    let mut path = if base.is_empty() {
+        format!("/metrics/job/{}", encode(job))
+    } else {
+        format!("/{}/metrics/job/{}", base, encode(job))
+    };
+    for tag in tags {
+        path.push_str(&format!("/{}/{}", tag.key, encode(&tag.value)));
+    }
+    let address = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+
+    let body = metrics.to_text();
+    let mut stream =
+        TcpStream::connect(&address).with_context(|| format!("cannot connect to {}", address))?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    write!(
+        stream,
+        "PUT {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; version=0.0.4\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        path,
+        host,
+        body.len(),
+        body
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(code) if code.starts_with('2') => Ok(()),
+        _ => bail!("Pushgateway answered {:?}", status_line.trim()),
+    }
+}
+
+/// Percent-encode a path segment.
+fn encode(segment: &str) -> String {
+    segment
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pushgateway::{push, Metrics};
+    use chrono::{Duration, Local, TimeZone};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    /// Accept a single request, answer it with `status` and return it.
+    fn mock_server(status: &'static str) -> (String, thread::JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/gateway", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.strip_prefix("Content-Length: ") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            request.push_str(&String::from_utf8(body).unwrap());
+            write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).unwrap();
+            request
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn push_metrics() {
+        let metrics = Metrics {
+            exit_code: 0,
+            duration: Duration::milliseconds(1_250),
+            last_success: Some(Local.timestamp_opt(1_700_000_000, 0).unwrap()),
+        };
+
+        let (url, server) = mock_server("200 OK");
+        let tags = [
+            "env=production".parse().unwrap(),
+            "team=back end".parse().unwrap(),
+        ];
+        push(&url, "nightly backup", &tags, &metrics).unwrap();
+        let request = server.join().unwrap();
+        assert!(request.starts_with(
+            "PUT /gateway/metrics/job/nightly%20backup/env/production/team/back%20end HTTP/1.1\r\n"
+        ));
+        assert!(request.ends_with(
+            "# TYPE cronthat_last_exit_code gauge\n\
+             cronthat_last_exit_code 0\n\
+             # TYPE cronthat_last_duration_seconds gauge\n\
+             cronthat_last_duration_seconds 1.250\n\
+             # TYPE cronthat_last_success_timestamp_seconds gauge\n\
+             cronthat_last_success_timestamp_seconds 1700000000\n"
+        ));
+
+        let (url, server) = mock_server("500 Internal Server Error");
+        push(&url, "job", &[], &metrics).expect_err("server error");
+        server.join().unwrap();
+        push("https://example.com", "job", &[], &metrics).expect_err("unsupported URL");
+    }
+}
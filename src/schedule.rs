@@ -0,0 +1,595 @@
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{
+    DateTime, Datelike, Days, Duration, FixedOffset, Local, NaiveTime, TimeZone, Timelike, Weekday,
+};
+use chrono_tz::Tz;
+use comfy_table::presets::ASCII_FULL_CONDENSED;
+use comfy_table::{Attribute, Cell, CellAlignment, Table};
+use cron::Schedule;
+use std::str::FromStr;
+
+/// A cron expression field with its valid range and the names it accepts instead of numbers,
+/// abbreviated or spelled out.
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+    full_names: &'static [&'static str],
+}
+
+static FIELDS: [Field; 7] = [
+    Field {
+        name: "seconds",
+        min: 0,
+        max: 59,
+        names: &[],
+        full_names: &[],
+    },
+    Field {
+        name: "minutes",
+        min: 0,
+        max: 59,
+        names: &[],
+        full_names: &[],
+    },
+    Field {
+        name: "hours",
+        min: 0,
+        max: 23,
+        names: &[],
+        full_names: &[],
+    },
+    Field {
+        name: "day of month",
+        min: 1,
+        max: 31,
+        names: &[],
+        full_names: &[],
+    },
+    Field {
+        name: "month",
+        min: 1,
+        max: 12,
+        names: &[
+            "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+        ],
+        full_names: &[
+            "JANUARY",
+            "FEBRUARY",
+            "MARCH",
+            "APRIL",
+            "MAY",
+            "JUNE",
+            "JULY",
+            "AUGUST",
+            "SEPTEMBER",
+            "OCTOBER",
+            "NOVEMBER",
+            "DECEMBER",
+        ],
+    },
+    Field {
+        name: "day of week",
+        min: 1,
+        max: 7,
+        names: &["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"],
+        full_names: &[
+            "SUNDAY",
+            "MONDAY",
+            "TUESDAY",
+            "WEDNESDAY",
+            "THURSDAY",
+            "FRIDAY",
+            "SATURDAY",
+        ],
+    },
+    Field {
+        name: "year",
+        min: 1970,
+        max: 2100,
+        names: &[],
+        full_names: &[],
+    },
+];
+
+/// Nickname of the expressions running once at startup.
+static REBOOT: &str = "@reboot";
+/// Schedule of `@reboot`, without any upcoming execution.
+static REBOOT_SCHEDULE: &str = "0 0 0 1 1 * 1970";
+
+/// Parse a cron expression, pointing at the offending field when it is invalid.
+pub fn parse(expression: &str) -> Result<Schedule> {
+    if is_reboot(expression) {
+        return Ok(Schedule::from_str(REBOOT_SCHEDULE)?);
+    }
+    Schedule::from_str(expression)
+        .map_err(|err| match validate_fields(expression) {
+            Err(field_err) => field_err,
+            Ok(()) => anyhow!(err),
+        })
+        .context("invalid cron expression")
+}
+
+/// Cron expression running at `at_time` (HH:MM or HH:MM:SS, midnight if not given) on the given
+/// days of month, months and days of week, each a list of values and ranges like "1,15",
+/// "jan-mar,dec" or "mon-fri". The names are case-insensitive and may be spelled out.
+pub fn compose(
+    at_time: Option<&str>,
+    days: Option<&str>,
+    months: Option<&str>,
+    weekdays: Option<&str>,
+) -> Result<String> {
+    let time = match at_time {
+        Some(at_time) => NaiveTime::parse_from_str(at_time, "%H:%M:%S")
+            .or_else(|_| NaiveTime::parse_from_str(at_time, "%H:%M"))
+            .with_context(|| {
+                format!(
+                    "invalid --at-time {:?}, expected HH:MM or HH:MM:SS",
+                    at_time
+                )
+            })?,
+        None => NaiveTime::MIN,
+    };
+    let field = |value: Option<&str>, field: &Field, option: &str| match value {
+        Some(value) => field
+            .normalize(value)
+            .with_context(|| format!("invalid --{} {:?}", option, value)),
+        None => Ok("*".to_string()),
+    };
+    Ok(format!(
+        "{} {} {} {} {} {}",
+        time.second(),
+        time.minute(),
+        time.hour(),
+        field(days, &FIELDS[3], "days")?,
+        field(months, &FIELDS[4], "months")?,
+        field(weekdays, &FIELDS[5], "weekdays")?
+    ))
+}
+
+/// Whether `expression` only runs once at startup, its schedule has no upcoming execution.
+pub fn is_reboot(expression: &str) -> bool {
+    expression.trim() == REBOOT
+}
+
+/// Next execution of `schedule` strictly after `from`, the cron expression being evaluated in
+/// `timezone` if given, otherwise in local time.
+pub fn next_after(
+    schedule: &Schedule,
+    from: &DateTime<Local>,
+    timezone: Option<Tz>,
+) -> Option<DateTime<Local>> {
+    match timezone {
+        Some(timezone) => schedule
+            .after(&from.with_timezone(&timezone))
+            .next()
+            .map(|datetime| datetime.with_timezone(&Local)),
+        None => schedule.after(from).next(),
+    }
+}
+
+/// Calendar of the executions in the 7 days starting today, one column per day from Monday to
+/// Sunday and one row per hour, `X` marking the hours with at least one execution. Today's
+/// column is highlighted.
+pub fn week_table(schedule: &Schedule, timezone: Option<Tz>, now: DateTime<Local>) -> Table {
+    let today = now.date_naive();
+    let days: Vec<_> = [
+        Weekday::Mon,
+        Weekday::Tue,
+        Weekday::Wed,
+        Weekday::Thu,
+        Weekday::Fri,
+        Weekday::Sat,
+        Weekday::Sun,
+    ]
+    .iter()
+    .map(|weekday| {
+        let ahead =
+            (weekday.num_days_from_monday() + 7 - today.weekday().num_days_from_monday()) % 7;
+        today + Days::new(ahead.into())
+    })
+    .collect();
+
+    let mut table = Table::new();
+    table.load_preset(ASCII_FULL_CONDENSED);
+    let mut header = vec![Cell::new("")];
+    header.extend(days.iter().map(|day| {
+        let cell = Cell::new(day.format("%a %m-%d"));
+        if *day == today {
+            cell.add_attribute(Attribute::Reverse)
+        } else {
+            cell
+        }
+    }));
+    table.set_header(header);
+
+    for hour in 0..24 {
+        let mut row = vec![Cell::new(format!("{:02}:00", hour))];
+        row.extend(days.iter().map(|day| {
+            let start = day
+                .and_hms_opt(hour, 0, 0)
+                .and_then(|start| Local.from_local_datetime(&start).earliest());
+            // Executions are looked up after the instant preceding the slot since `after` is
+            // exclusive, only the ones still ahead of `now` are shown.
+            let scheduled = start.is_some_and(|start| {
+                let from = start.max(now) - Duration::seconds(1);
+                next_after(schedule, &from, timezone)
+                    .is_some_and(|next| next >= now && next < start + Duration::hours(1))
+            });
+            let cell =
+                Cell::new(if scheduled { "X" } else { "-" }).set_alignment(CellAlignment::Center);
+            if *day == today {
+                cell.add_attribute(Attribute::Bold)
+            } else {
+                cell
+            }
+        }));
+        table.add_row(row);
+    }
+    table
+}
+
+/// Unified-diff-style listing of the fire times removed from (`-`) and added to (`+`) the `old`
+/// plan by the `new` one, both sorted. Unchanged times are only counted. The times are compared
+/// as instants, so that plans on either side of a DST change line up.
+pub fn diff_plans(old: &[DateTime<FixedOffset>], new: &[DateTime<FixedOffset>]) -> String {
+    let mut lines = Vec::new();
+    let (mut old, mut new) = (old.iter().peekable(), new.iter().peekable());
+    let mut unchanged = 0;
+    loop {
+        match (old.peek(), new.peek()) {
+            (Some(removed), Some(added)) if removed == added => {
+                unchanged += 1;
+                old.next();
+                new.next();
+            }
+            (Some(removed), Some(added)) if removed < added => {
+                lines.push(format!("-{}", removed.to_rfc3339()));
+                old.next();
+            }
+            (_, Some(added)) => {
+                lines.push(format!("+{}", added.to_rfc3339()));
+                new.next();
+            }
+            (Some(removed), None) => {
+                lines.push(format!("-{}", removed.to_rfc3339()));
+                old.next();
+            }
+            (None, None) => break,
+        }
+    }
+    lines.push(format!("{} unchanged", unchanged));
+    lines.join("\n")
+}
+
+/// Check each field of `expression` against its range, the error shows the expression with the
+/// invalid field underlined.
+fn validate_fields(expression: &str) -> Result<()> {
+    let fields: Vec<(usize, &str)> = expression
+        .split_whitespace()
+        .map(|field| {
+            (
+                field.as_ptr() as usize - expression.as_ptr() as usize,
+                field,
+            )
+        })
+        .collect();
+    if !(6..=7).contains(&fields.len()) {
+        bail!(
+            "expected 6 or 7 fields (seconds, minutes, hours, day of month, month, day of week \
+             and optionally year), got {}",
+            fields.len()
+        );
+    }
+    for (i, (field, (offset, value))) in FIELDS.iter().zip(fields).enumerate() {
+        if let Err(err) = field.validate(value) {
+            bail!(
+                "field {} ({}): {}\n  {}\n  {}{}",
+                i + 1,
+                field.name,
+                err,
+                expression,
+                " ".repeat(expression[..offset].chars().count()),
+                "^".repeat(value.chars().count())
+            );
+        }
+    }
+    Ok(())
+}
+
+impl Field {
+    fn validate(&self, value: &str) -> Result<()> {
+        for item in value.split(',') {
+            let (range, step) = match item.split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item, None),
+            };
+            if let Some(step) = step {
+                if !step.parse::<u32>().is_ok_and(|step| step > 0) {
+                    bail!("invalid step {:?}", step);
+                }
+            }
+            if range == "*" || (range == "?" && self.names.len() == 7) {
+                continue;
+            }
+            match range.split_once('-') {
+                Some((start, end)) => {
+                    let (start, end) = (self.value(start)?, self.value(end)?);
+                    if start > end {
+                        bail!("decreasing range {:?}", range);
+                    }
+                }
+                None => {
+                    self.value(range)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `value` with the names replaced by their cron abbreviation, e.g. "Monday-fri" by
+    /// "MON-FRI".
+    fn normalize(&self, value: &str) -> Result<String> {
+        let mut items = Vec::new();
+        for item in value.split(',') {
+            let (range, step) = match item.trim().split_once('/') {
+                Some((range, step)) => (range, Some(step)),
+                None => (item.trim(), None),
+            };
+            let mut bounds = Vec::new();
+            for bound in range.split('-') {
+                if bound == "*" || bound.parse::<u32>().is_ok() {
+                    bounds.push(bound.to_string());
+                } else {
+                    let position = self.value(bound)? - self.min;
+                    bounds.push(self.names[position as usize].to_string());
+                }
+            }
+            let mut item = bounds.join("-");
+            if let Some(step) = step {
+                item = format!("{}/{}", item, step);
+            }
+            items.push(item);
+        }
+        let normalized = items.join(",");
+        self.validate(&normalized)?;
+        Ok(normalized)
+    }
+
+    fn value(&self, value: &str) -> Result<u32> {
+        if let Ok(number) = value.parse::<u32>() {
+            if !(self.min..=self.max).contains(&number) {
+                bail!("value {} out of range {}-{}", number, self.min, self.max);
+            }
+            return Ok(number);
+        }
+        let upper = value.to_uppercase();
+        self.names
+            .iter()
+            .zip(self.full_names)
+            .position(|(name, full_name)| upper == *name || upper == *full_name)
+            .map(|position| self.min + position as u32)
+            .with_context(|| {
+                if self.names.is_empty() {
+                    format!("invalid value {:?}", value)
+                } else {
+                    format!(
+                        "invalid value {:?}, expected {}-{} or one of {}",
+                        value,
+                        self.min,
+                        self.max,
+                        self.names.join(", ")
+                    )
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::schedule::{compose, diff_plans, next_after, parse, week_table};
+    use chrono::{DateTime, Local, TimeZone, Utc};
+
+    #[test]
+    fn parse_highlights_invalid_field() {
+        parse("0 30 9 * * MON-FRI").unwrap();
+
+        let err = parse("0 0 25 * * *").unwrap_err();
+        assert_eq!(err.to_string(), "invalid cron expression");
+        assert_eq!(
+            err.root_cause().to_string(),
+            "field 3 (hours): value 25 out of range 0-23\n  0 0 25 * * *\n      ^^"
+        );
+
+        let err = parse("0 0 12 * * MON-FUN").unwrap_err();
+        assert_eq!(
+            err.root_cause().to_string(),
+            "field 6 (day of week): invalid value \"FUN\", expected 1-7 or one of \
+             SUN, MON, TUE, WED, THU, FRI, SAT\n  0 0 12 * * MON-FUN\n             ^^^^^^^"
+        );
+
+        let err = parse("0 0 12 * *").unwrap_err();
+        assert!(err
+            .root_cause()
+            .to_string()
+            .starts_with("expected 6 or 7 fields"));
+    }
+
+    #[test]
+    fn compose_expression() {
+        let compose = |at_time, days, months, weekdays| {
+            let expression = compose(at_time, days, months, weekdays)?;
+            parse(&expression)?;
+            anyhow::Ok(expression)
+        };
+        assert_eq!(
+            compose(Some("09:00"), None, None, Some("mon-fri")).unwrap(),
+            "0 0 9 * * MON-FRI"
+        );
+        assert_eq!(
+            compose(
+                Some("17:30:15"),
+                Some("1,15"),
+                Some("January-mar,dec"),
+                None
+            )
+            .unwrap(),
+            "15 30 17 1,15 JAN-MAR,DEC *"
+        );
+        assert_eq!(
+            compose(None, None, None, Some("Saturday, sun")).unwrap(),
+            "0 0 0 * * SAT,SUN"
+        );
+        assert_eq!(
+            compose(None, Some("1-31/2"), None, None).unwrap(),
+            "0 0 0 1-31/2 * *"
+        );
+
+        let error = |at_time, days, months, weekdays| {
+            format!(
+                "{:#}",
+                compose(at_time, days, months, weekdays).unwrap_err()
+            )
+        };
+        assert_eq!(
+            error(Some("9h"), None, None, None),
+            "invalid --at-time \"9h\", expected HH:MM or HH:MM:SS: input contains invalid characters"
+        );
+        assert_eq!(
+            error(None, None, None, Some("fri-mon")),
+            "invalid --weekdays \"fri-mon\": decreasing range \"FRI-MON\""
+        );
+        assert_eq!(
+            error(None, Some("0"), None, None),
+            "invalid --days \"0\": value 0 out of range 1-31"
+        );
+        for (months, weekdays) in [(None, Some("MONKEY")), (Some("janitor"), None)] {
+            assert!(
+                error(None, None, months, weekdays).contains("invalid value"),
+                "{:?} {:?}",
+                months,
+                weekdays
+            );
+        }
+    }
+
+    #[test]
+    fn parse_field_errors() {
+        let error = |expression| {
+            let err = parse(expression).unwrap_err().root_cause().to_string();
+            err.lines().next().unwrap().to_string()
+        };
+        assert_eq!(
+            error("0 0 12 1,32 * *"),
+            "field 4 (day of month): value 32 out of range 1-31"
+        );
+        assert_eq!(
+            error("0 0 12 * 0 *"),
+            "field 5 (month): value 0 out of range 1-12"
+        );
+        assert_eq!(
+            error("0 0 12 * * * 1969"),
+            "field 7 (year): value 1969 out of range 1970-2100"
+        );
+        assert_eq!(
+            error("0 x 12 * * *"),
+            "field 2 (minutes): invalid value \"x\""
+        );
+        assert_eq!(
+            error("*/0 0 12 * * *"),
+            "field 1 (seconds): invalid step \"0\""
+        );
+        assert_eq!(
+            error("0 0 18-9 * * *"),
+            "field 3 (hours): decreasing range \"18-9\""
+        );
+        assert_eq!(
+            error("0 0 12 * * * * 0"),
+            "expected 6 or 7 fields (seconds, minutes, hours, day of month, month, day of week \
+             and optionally year), got 8"
+        );
+    }
+
+    #[test]
+    fn week_table_marks_scheduled_hours() {
+        let schedule = parse("0 30 9 * * Mon-Fri").unwrap();
+        // A Wednesday, after 09:30.
+        let now = Local.with_ymd_and_hms(2025, 1, 8, 12, 0, 0).unwrap();
+        let mut table = week_table(&schedule, None, now);
+        table.force_no_tty();
+        let rendered = table.to_string();
+
+        assert!(rendered.contains("Mon 01-13"));
+        assert!(rendered.contains("Wed 01-08"));
+        assert!(rendered.contains("Sun 01-12"));
+        let row = |hour: &str| {
+            rendered
+                .lines()
+                .find(|line| line.contains(hour))
+                .unwrap()
+                .split('|')
+                .map(str::trim)
+                .filter(|cell| !cell.is_empty())
+                .skip(1)
+                .collect::<Vec<_>>()
+                .join("")
+        };
+        // Monday to Sunday, today's execution already happened.
+        assert_eq!(row("09:00"), "XX-XX--");
+        assert_eq!(row("10:00"), "-------");
+    }
+
+    #[test]
+    fn next_after_in_timezone() {
+        let schedule = parse("0 0 9 * * *").unwrap();
+        // 21:00 in Tokyo.
+        let from = Utc.with_ymd_and_hms(2025, 1, 2, 12, 0, 0).unwrap();
+        let tokyo = "Asia/Tokyo".parse().unwrap();
+        assert_eq!(
+            next_after(&schedule, &from.with_timezone(&Local), Some(tokyo)).unwrap(),
+            Utc.with_ymd_and_hms(2025, 1, 3, 0, 0, 0).unwrap()
+        );
+        let from = Local.with_ymd_and_hms(2025, 1, 2, 12, 0, 0).unwrap();
+        assert_eq!(
+            next_after(&schedule, &from, None).unwrap(),
+            Local.with_ymd_and_hms(2025, 1, 3, 9, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn diff_plans_lists_changes() {
+        let plan = |times: &[&str]| {
+            times
+                .iter()
+                .map(|time| DateTime::parse_from_rfc3339(time).unwrap())
+                .collect::<Vec<_>>()
+        };
+        let old = plan(&[
+            "2025-01-02T09:00:00+01:00",
+            "2025-01-02T12:00:00+01:00",
+            "2025-01-03T09:00:00+01:00",
+        ]);
+        let new = plan(&[
+            "2025-01-02T12:00:00+01:00",
+            "2025-01-02T15:00:00+01:00",
+            "2025-01-03T09:00:00+01:00",
+        ]);
+        assert_eq!(
+            diff_plans(&old, &new),
+            "-2025-01-02T09:00:00+01:00\n+2025-01-02T15:00:00+01:00\n2 unchanged"
+        );
+        assert_eq!(
+            diff_plans(&[], &new[..1]),
+            "+2025-01-02T12:00:00+01:00\n0 unchanged"
+        );
+
+        // 02:30 before the clocks go back is earlier than 02:15 after, unlike their text.
+        let old = plan(&["2025-10-26T02:30:00+02:00", "2025-10-26T03:00:00+01:00"]);
+        let new = plan(&["2025-10-26T02:15:00+01:00", "2025-10-26T03:00:00+01:00"]);
+        assert_eq!(
+            diff_plans(&old, &new),
+            "-2025-10-26T02:30:00+02:00\n+2025-10-26T02:15:00+01:00\n1 unchanged"
+        );
+    }
+}
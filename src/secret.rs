@@ -0,0 +1,108 @@
+use crate::log::warning;
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// Environment variable of the command read from a file, written `NAME=PATH`, e.g. a Docker
+/// secret mounted in /run/secrets.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct EnvFile {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+/// Read the trimmed content of every file into its variable. Missing files are skipped with a
+/// warning if `optional`, an error otherwise.
+pub fn read_env_files(env_files: &[EnvFile], optional: bool) -> Result<HashMap<String, String>> {
+    let mut env = HashMap::new();
+    for env_file in env_files {
+        match fs::read_to_string(&env_file.path) {
+            Ok(content) => {
+                env.insert(env_file.name.clone(), content.trim().to_string());
+            }
+            Err(err) if err.kind() == ErrorKind::NotFound && optional => {
+                warning!(
+                    "warning: {:?} not found, {} is not set",
+                    env_file.path,
+                    env_file.name
+                );
+            }
+            Err(err) => {
+                return Err(err).with_context(|| {
+                    format!("cannot read {:?} for {}", env_file.path, env_file.name)
+                })
+            }
+        }
+    }
+    Ok(env)
+}
+
+impl FromStr for EnvFile {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<EnvFile> {
+        let (name, path) = value
+            .split_once('=')
+            .context("expected a variable formatted as NAME=PATH")?;
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            bail!("invalid variable name {:?}", name);
+        }
+        if path.is_empty() {
+            bail!("empty path for variable {:?}", name);
+        }
+        Ok(EnvFile {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl TryFrom<String> for EnvFile {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<EnvFile> {
+        value.parse()
+    }
+}
+
+impl From<EnvFile> for String {
+    fn from(env_file: EnvFile) -> String {
+        format!("{}={}", env_file.name, env_file.path.display())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::secret::{read_env_files, EnvFile};
+    use std::io::Write;
+
+    #[test]
+    fn read_env_files_trimmed() {
+        let mut secret = tempfile::NamedTempFile::new().unwrap();
+        writeln!(secret, "s3cr3t").unwrap();
+        let env_files: Vec<EnvFile> = vec![
+            format!("DB_PASSWORD={}", secret.path().display())
+                .parse()
+                .unwrap(),
+            "API_TOKEN=/nonexistent/api_token".parse().unwrap(),
+        ];
+
+        let env = read_env_files(&env_files, true).unwrap();
+        assert_eq!(env["DB_PASSWORD"], "s3cr3t");
+        assert!(!env.contains_key("API_TOKEN"));
+        read_env_files(&env_files, false).expect_err("missing file");
+
+        "DB_PASSWORD"
+            .parse::<EnvFile>()
+            .expect_err("missing separator");
+        "=/run/secrets/db"
+            .parse::<EnvFile>()
+            .expect_err("empty name");
+        "DB_PASSWORD=".parse::<EnvFile>().expect_err("empty path");
+    }
+}
@@ -0,0 +1,48 @@
+use crate::log::warning;
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+/// Graceful shutdown signal shared between the scheduling loops, usually triggered by `CTRL-C`.
+#[derive(Clone, Default)]
+pub struct Shutdown(Arc<(Mutex<bool>, Condvar)>);
+
+static CTRL_C: OnceLock<Shutdown> = OnceLock::new();
+
+impl Shutdown {
+    /// Process-wide shutdown triggered by `CTRL-C`, the handler is installed on first use.
+    pub fn on_ctrl_c() -> &'static Shutdown {
+        CTRL_C.get_or_init(|| {
+            let shutdown = Shutdown::default();
+            let handler_shutdown = shutdown.clone();
+            if let Err(err) = ctrlc::set_handler(move || handler_shutdown.trigger()) {
+                warning!("warning: cannot handle CTRL-C gracefully: {}", err);
+            }
+            shutdown
+        })
+    }
+
+    pub fn trigger(&self) {
+        let (triggered, condvar) = &*self.0;
+        *triggered.lock().unwrap() = true;
+        condvar.notify_all();
+    }
+
+    pub fn is_triggered(&self) -> bool {
+        *self.0 .0.lock().unwrap()
+    }
+
+    /// Sleep for `duration` unless the shutdown is triggered first, returns whether it was.
+    pub fn sleep(&self, duration: Duration) -> bool {
+        let deadline = Instant::now() + duration;
+        let (triggered, condvar) = &*self.0;
+        let mut guard = triggered.lock().unwrap();
+        while !*guard {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            guard = condvar.wait_timeout(guard, deadline - now).unwrap().0;
+        }
+        *guard
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+/// Minimal scheduler state persisted between restarts with `--state-file`.
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    /// Number of runs already executed, immediate ones included, counted towards `--repetitions`.
+    pub runs: usize,
+    /// When the command was last spawned.
+    pub last_run: Option<DateTime<Local>>,
+    /// When the command last succeeded.
+    pub last_success: Option<DateTime<Local>>,
+    /// Total number of failed runs.
+    pub failures: usize,
+    /// Number of failed runs since the last successful one.
+    pub consecutive_failures: usize,
+}
+
+impl State {
+    /// Load the state from `path`, starting from a blank state if the file does not exist yet.
+    pub fn load(path: &Path) -> Result<State> {
+        if !path.exists() {
+            return Ok(State::default());
+        }
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("cannot read state file {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("invalid state file {:?}", path))
+    }
+
+    /// Write the state to `path` atomically.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        write_atomically(path, serde_json::to_string(self)?)
+    }
+
+    pub fn record(&mut self, succeeded: bool) {
+        self.last_run = Some(Local::now());
+        if succeeded {
+            self.last_success = self.last_run;
+            self.consecutive_failures = 0;
+        } else {
+            self.failures += 1;
+            self.consecutive_failures += 1;
+        }
+    }
+}
+
+/// Details of a failed execution written to `--failure-file`.
+#[derive(Serialize)]
+pub struct Failure<'a> {
+    pub timestamp: DateTime<Local>,
+    pub exit_code: i32,
+    pub command: &'a str,
+    /// Number of the run, as counted toward `--repetitions`.
+    pub run_number: usize,
+}
+
+/// Write `contents` to `path` by renaming a temporary sibling file so that readers never see a
+/// partially written file.
+pub fn write_atomically(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let mut tmp_name = path.file_name().context("invalid file path")?.to_owned();
+    tmp_name.push(".tmp");
+    let tmp_path = path.with_file_name(tmp_name);
+    fs::write(&tmp_path, contents).with_context(|| format!("cannot write {:?}", tmp_path))?;
+    fs::rename(&tmp_path, path).with_context(|| format!("cannot write {:?}", path))
+}
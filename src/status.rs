@@ -0,0 +1,137 @@
+use crate::log::warning;
+use std::collections::VecDeque;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// How long a status request may take to be sent or its response to be read.
+static REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Last lines of the command's output, shared with the status endpoint. It is filled once each
+/// execution finishes, the output of the running one is not in it yet.
+#[derive(Clone)]
+pub struct LogBuffer {
+    lines: Arc<Mutex<VecDeque<String>>>,
+    capacity: usize,
+}
+
+impl LogBuffer {
+    pub fn new(capacity: usize) -> LogBuffer {
+        LogBuffer {
+            lines: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            capacity,
+        }
+    }
+
+    /// Append every line of `output`, dropping the oldest ones beyond the capacity.
+    pub fn push_output(&self, output: &str) {
+        let mut lines = self.lines.lock().unwrap();
+        for line in output.lines() {
+            if lines.len() == self.capacity {
+                if self.capacity == 0 {
+                    return;
+                }
+                lines.pop_front();
+            }
+            lines.push_back(line.to_string());
+        }
+    }
+
+    pub fn lines(&self) -> Vec<String> {
+        self.lines.lock().unwrap().iter().cloned().collect()
+    }
+}
+
+/// Answer the status requests received by `listener` from background threads, `/logs` returns
+/// the content of `buffer` as plain text. Each connection has its own thread and times out so
+/// that a silent client doesn't block the others.
+pub fn serve(listener: TcpListener, buffer: LogBuffer) {
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let buffer = buffer.clone();
+            thread::spawn(move || {
+                if let Err(err) = respond(stream, &buffer) {
+                    warning!("warning: cannot answer status request: {}", err);
+                }
+            });
+        }
+    });
+}
+
+fn respond(stream: TcpStream, buffer: &LogBuffer) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    stream.set_write_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // Read the headers before answering so that the client doesn't see the connection reset.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+
+    let (status, body) = match request_line.split_whitespace().nth(1) {
+        Some("/logs") => {
+            let mut body = buffer.lines().join("\n");
+            if !body.is_empty() {
+                body.push('\n');
+            }
+            ("200 OK", body)
+        }
+        _ => ("404 Not Found", "not found\n".to_string()),
+    };
+    write!(
+        &stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::status::{serve, LogBuffer};
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    fn get(address: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: {}\r\n\r\n", path, address).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn log_buffer_keeps_last_lines() {
+        let buffer = LogBuffer::new(3);
+        buffer.push_output("one\ntwo\n");
+        buffer.push_output("three\nfour\nfive\n");
+        assert_eq!(buffer.lines(), vec!["three", "four", "five"]);
+
+        let buffer = LogBuffer::new(0);
+        buffer.push_output("one\n");
+        assert!(buffer.lines().is_empty());
+    }
+
+    #[test]
+    fn serve_logs() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let buffer = LogBuffer::new(2);
+        serve(listener, buffer.clone());
+
+        buffer.push_output("one\ntwo\nthree\n");
+        // A client that never sends its request doesn't block the others.
+        let _silent = TcpStream::connect(&address).unwrap();
+        let response = get(&address, "/logs");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\ntwo\nthree\n"));
+        assert!(get(&address, "/").starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,74 @@
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Metadata attached to the events sent by cronthat, written `key=value`. Keys follow the
+/// Prometheus label naming rules so that they can be used as labels as is.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Tag {
+    pub key: String,
+    pub value: String,
+}
+
+impl FromStr for Tag {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Tag> {
+        let (key, tag_value) = value
+            .split_once('=')
+            .context("expected a tag formatted as KEY=VALUE")?;
+        let mut chars = key.chars();
+        let valid_key = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid_key {
+            bail!(
+                "invalid tag key {:?}, expected letters, digits and underscores",
+                key
+            );
+        }
+        if tag_value.is_empty() {
+            bail!("empty value for tag {:?}", key);
+        }
+        Ok(Tag {
+            key: key.to_string(),
+            value: tag_value.to_string(),
+        })
+    }
+}
+
+impl TryFrom<String> for Tag {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Tag> {
+        value.parse()
+    }
+}
+
+impl From<Tag> for String {
+    fn from(tag: Tag) -> String {
+        format!("{}={}", tag.key, tag.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tag::Tag;
+
+    #[test]
+    fn parse_tags() {
+        let tag: Tag = "env=production".parse().unwrap();
+        assert_eq!(tag.key, "env");
+        assert_eq!(tag.value, "production");
+        let tag: Tag = "_query=a=b".parse().unwrap();
+        assert_eq!(tag.value, "a=b");
+
+        "env".parse::<Tag>().expect_err("missing separator");
+        "=production".parse::<Tag>().expect_err("empty key");
+        "1env=production".parse::<Tag>().expect_err("invalid key");
+        "team-name=backend".parse::<Tag>().expect_err("invalid key");
+        "env=".parse::<Tag>().expect_err("empty value");
+    }
+}
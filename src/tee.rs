@@ -0,0 +1,135 @@
+use anyhow::Result;
+use std::path::PathBuf;
+
+/// Copy of everything written to stdout and stderr, cronthat's own lines and the output of the
+/// commands it spawns, into files. The original stdout and stderr are restored when it is
+/// dropped.
+pub struct Tee {
+    #[cfg(unix)]
+    streams: Vec<Stream>,
+}
+
+/// One of the redirected file descriptors and the thread copying its pipe.
+#[cfg(unix)]
+struct Stream {
+    fd: libc::c_int,
+    saved: libc::c_int,
+    copier: Option<std::thread::JoinHandle<()>>,
+}
+
+/// Redirect stdout and stderr into pipes whose content is written both to the original stream
+/// and to the files at `paths` (appended to), `None` if there are none.
+#[cfg(unix)]
+pub fn start(paths: &[PathBuf]) -> Result<Option<Tee>> {
+    use anyhow::Context;
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    let files = paths
+        .iter()
+        .map(|path| {
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("cannot open --tee file {:?}", path))
+        })
+        .collect::<Result<Vec<File>>>()?;
+
+    std::io::stdout().flush()?;
+    std::io::stderr().flush()?;
+    let mut tee = Tee {
+        streams: Vec::new(),
+    };
+    for fd in [1, 2] {
+        let files = files
+            .iter()
+            .map(File::try_clone)
+            .collect::<std::io::Result<Vec<File>>>()?;
+        // Dropping `tee` restores the streams already redirected if this one fails.
+        tee.streams.push(redirect(fd, files)?);
+    }
+    Ok(Some(tee))
+}
+
+/// Redirect `fd` into a pipe copied to the original `fd` and to `files`.
+#[cfg(unix)]
+fn redirect(fd: libc::c_int, mut files: Vec<std::fs::File>) -> Result<Stream> {
+    use anyhow::Context;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::unix::io::FromRawFd;
+
+    let mut fds = [0; 2];
+    // SAFETY: `fds` has room for the two file descriptors written by pipe, the others are valid
+    // for the duration of the calls.
+    let (saved, original) = unsafe {
+        if libc::pipe(fds.as_mut_ptr()) == -1 {
+            return Err(std::io::Error::last_os_error()).context("cannot create --tee pipe");
+        }
+        let saved = libc::dup(fd);
+        let original = libc::dup(fd);
+        libc::dup2(fds[1], fd);
+        libc::close(fds[1]);
+        // Not inherited by the commands, which must only get the write end as their output.
+        for fd in [fds[0], saved, original] {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        (saved, original)
+    };
+    // SAFETY: both file descriptors were just created and are owned by the files from now on.
+    let (mut pipe, mut original) =
+        unsafe { (File::from_raw_fd(fds[0]), File::from_raw_fd(original)) };
+
+    let copier = std::thread::spawn(move || {
+        let mut buffer = [0; 8192];
+        loop {
+            let read = match pipe.read(&mut buffer) {
+                Ok(0) | Err(_) => break,
+                Ok(read) => read,
+            };
+            let _ = original.write_all(&buffer[..read]);
+            for file in &mut files {
+                let _ = file.write_all(&buffer[..read]);
+            }
+        }
+    });
+    Ok(Stream {
+        fd,
+        saved,
+        copier: Some(copier),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn start(paths: &[PathBuf]) -> Result<Option<Tee>> {
+    if paths.is_empty() {
+        return Ok(None);
+    }
+    anyhow::bail!("--tee is only supported on Unix")
+}
+
+#[cfg(unix)]
+impl Drop for Tee {
+    fn drop(&mut self) {
+        use std::io::Write;
+
+        let _ = std::io::stdout().flush();
+        let _ = std::io::stderr().flush();
+        for stream in &mut self.streams {
+            // SAFETY: `saved` is a valid file descriptor owned by the tee. Restoring it closes
+            // the pipe's last write end, unless a command still running holds it, so that the
+            // copier reaches the end of the pipe.
+            unsafe {
+                libc::dup2(stream.saved, stream.fd);
+                libc::close(stream.saved);
+            }
+            if let Some(copier) = stream.copier.take() {
+                let _ = copier.join();
+            }
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::{bail, Result};
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, Local, SecondsFormat};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// How the timestamps printed by cronthat are formatted.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum TimestampFormat {
+    /// ISO 8601 to the second, e.g. 2025-01-02T15:04:00+01:00
+    Iso,
+    /// Milliseconds since the Unix epoch
+    Epoch,
+    /// RFC 3339 to the millisecond, e.g. 2025-01-02T15:04:00.123+01:00
+    Rfc3339,
+    /// strftime format, e.g. "%H:%M:%S"
+    Custom(String),
+}
+
+impl TimestampFormat {
+    pub fn format(&self, datetime: &DateTime<Local>) -> String {
+        match self {
+            TimestampFormat::Iso => datetime.format("%Y-%m-%dT%H:%M:%S%:z").to_string(),
+            TimestampFormat::Epoch => datetime.timestamp_millis().to_string(),
+            TimestampFormat::Rfc3339 => datetime.to_rfc3339_opts(SecondsFormat::Millis, false),
+            TimestampFormat::Custom(format) => datetime.format(format).to_string(),
+        }
+    }
+}
+
+impl FromStr for TimestampFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<TimestampFormat> {
+        Ok(match value {
+            "iso" => TimestampFormat::Iso,
+            "epoch" => TimestampFormat::Epoch,
+            "rfc3339" => TimestampFormat::Rfc3339,
+            _ => {
+                if StrftimeItems::new(value).any(|item| item == Item::Error) {
+                    bail!(
+                        "invalid timestamp format {:?}, expected iso, epoch, rfc3339 or a \
+                         strftime format",
+                        value
+                    );
+                }
+                TimestampFormat::Custom(value.to_string())
+            }
+        })
+    }
+}
+
+impl TryFrom<String> for TimestampFormat {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<TimestampFormat> {
+        value.parse()
+    }
+}
+
+impl From<TimestampFormat> for String {
+    fn from(format: TimestampFormat) -> String {
+        match format {
+            TimestampFormat::Iso => "iso".to_string(),
+            TimestampFormat::Epoch => "epoch".to_string(),
+            TimestampFormat::Rfc3339 => "rfc3339".to_string(),
+            TimestampFormat::Custom(format) => format,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timestamp::TimestampFormat;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn timestamp_formats() {
+        let datetime = Local.with_ymd_and_hms(2025, 1, 2, 15, 4, 0).unwrap();
+        let format = |value: &str| value.parse::<TimestampFormat>().unwrap().format(&datetime);
+        assert!(format("iso").starts_with("2025-01-02T15:04:00"));
+        assert_eq!(format("epoch"), datetime.timestamp_millis().to_string());
+        assert!(format("rfc3339").starts_with("2025-01-02T15:04:00.000"));
+        assert_eq!(format("%d/%m %H:%M"), "02/01 15:04");
+
+        "%Y-%m-%d %Q"
+            .parse::<TimestampFormat>()
+            .expect_err("invalid specifier");
+    }
+}
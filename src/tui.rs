@@ -0,0 +1,324 @@
+use crate::duration::format_duration;
+use crate::log::warning;
+#[cfg(unix)]
+use crate::shutdown::Shutdown;
+use crate::timestamp::TimestampFormat;
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Paragraph, Row, Table};
+use ratatui::Frame;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+/// Number of executions listed in the history.
+static HISTORY_SIZE: usize = 20;
+/// How often the dashboard is redrawn.
+#[cfg(unix)]
+static REFRESH_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+static TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Execution listed in the history.
+struct Execution {
+    started: DateTime<Local>,
+    duration: Duration,
+    exit_code: i32,
+    succeeded: bool,
+}
+
+#[derive(Default)]
+struct Board {
+    command: String,
+    next_execution: Option<DateTime<Local>>,
+    running_since: Option<DateTime<Local>>,
+    successes: usize,
+    failures: usize,
+    history: VecDeque<Execution>,
+}
+
+/// State of the scheduling loop shown by `--tui`, shared with the thread drawing it.
+#[derive(Clone, Default)]
+pub struct Dashboard {
+    board: Arc<Mutex<Board>>,
+    /// --timestamp-format, or `TIME_FORMAT` if not set.
+    time_format: Option<TimestampFormat>,
+}
+
+impl Dashboard {
+    pub fn new(time_format: Option<TimestampFormat>) -> Dashboard {
+        Dashboard {
+            board: Arc::default(),
+            time_format,
+        }
+    }
+
+    fn format_time(&self, datetime: &DateTime<Local>) -> String {
+        match &self.time_format {
+            Some(format) => format.format(datetime),
+            None => datetime.format(TIME_FORMAT).to_string(),
+        }
+    }
+
+    pub fn scheduled(&self, next_execution: DateTime<Local>) {
+        self.board.lock().unwrap().next_execution = Some(next_execution);
+    }
+
+    pub fn started(&self, command: &str) {
+        let mut board = self.board.lock().unwrap();
+        board.command = command.to_string();
+        board.running_since = Some(Local::now());
+    }
+
+    pub fn finished(&self, exit_code: i32, succeeded: bool) {
+        let mut board = self.board.lock().unwrap();
+        let Some(started) = board.running_since.take() else {
+            return;
+        };
+        if succeeded {
+            board.successes += 1;
+        } else {
+            board.failures += 1;
+        }
+        if board.history.len() == HISTORY_SIZE {
+            board.history.pop_back();
+        }
+        board.history.push_front(Execution {
+            started,
+            duration: Local::now() - started,
+            exit_code,
+            succeeded,
+        });
+    }
+
+    /// Draw the status, the counts and the history of the executions as of `now`.
+    fn draw(&self, frame: &mut Frame, now: DateTime<Local>) {
+        let board = self.board.lock().unwrap();
+        let status = match (board.running_since, board.next_execution) {
+            (Some(since), _) => format!("running for {}", format_duration(seconds(now - since))),
+            (None, Some(next)) => format!(
+                "next execution at {} (in {})",
+                self.format_time(&next),
+                format_duration(seconds(next - now))
+            ),
+            (None, None) => "starting".to_string(),
+        };
+        let summary = Paragraph::new(vec![
+            Line::from(format!("Command:    {}", board.command)),
+            Line::from(format!("Status:     {}", status)),
+            Line::from(vec![
+                "Executions: ".into(),
+                format!("{} succeeded", board.successes).fg(Color::Green),
+                ", ".into(),
+                format!("{} failed", board.failures).fg(Color::Red),
+            ]),
+        ])
+        .block(Block::bordered().title(" cronthat "));
+
+        let started: Vec<String> = board
+            .history
+            .iter()
+            .map(|execution| self.format_time(&execution.started))
+            .collect();
+        let started_width = started.iter().map(String::len).max().unwrap_or(0).max(19) + 1;
+        let rows = board
+            .history
+            .iter()
+            .zip(started)
+            .map(|(execution, started)| {
+                let style = match execution.succeeded {
+                    true => Style::new().fg(Color::Green),
+                    false => Style::new().fg(Color::Red),
+                };
+                Row::new(vec![
+                    started,
+                    format_duration(execution.duration),
+                    execution.exit_code.to_string(),
+                ])
+                .style(style)
+            });
+        let history = Table::new(
+            rows,
+            [
+                Constraint::Length(started_width as u16),
+                Constraint::Length(12),
+                Constraint::Length(9),
+            ],
+        )
+        .header(Row::new(vec!["Started", "Duration", "Exit code"]).bold())
+        .block(Block::bordered().title(" History "));
+
+        let [top, bottom] =
+            Layout::vertical([Constraint::Length(5), Constraint::Min(0)]).areas(frame.area());
+        frame.render_widget(summary, top);
+        frame.render_widget(history, bottom);
+    }
+}
+
+/// `duration` rounded up to the second for the countdowns.
+fn seconds(duration: Duration) -> Duration {
+    Duration::seconds((duration.num_milliseconds().max(0) + 999) / 1000)
+}
+
+/// Dashboard drawn on the terminal while stdout and stderr are discarded, both are restored when
+/// it is dropped.
+pub struct Tui {
+    pub dashboard: Dashboard,
+    #[cfg(unix)]
+    saved: [libc::c_int; 2],
+    #[cfg(unix)]
+    stop: Shutdown,
+    #[cfg(unix)]
+    drawer: Option<std::thread::JoinHandle<()>>,
+}
+
+/// Draw a dashboard on the terminal every second with the times in `time_format`, `None` when
+/// stdout is not a terminal.
+#[cfg(unix)]
+pub fn start(time_format: Option<TimestampFormat>) -> Result<Option<Tui>> {
+    use anyhow::Context;
+    use ratatui::backend::CrosstermBackend;
+    use ratatui::crossterm::cursor::{Hide, Show};
+    use ratatui::crossterm::execute;
+    use ratatui::crossterm::terminal::{EnterAlternateScreen, LeaveAlternateScreen};
+    use ratatui::Terminal;
+    use std::fs::File;
+    use std::io::{IsTerminal, Write};
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    if !std::io::stdout().is_terminal() {
+        warning!("warning: stdout is not a terminal, --tui is ignored");
+        return Ok(None);
+    }
+    let null = File::options()
+        .write(true)
+        .open("/dev/null")
+        .context("cannot open /dev/null")?;
+    std::io::stdout().flush()?;
+    // SAFETY: the file descriptors are valid for the duration of the calls.
+    let (saved, screen) = unsafe {
+        let saved = [libc::dup(1), libc::dup(2)];
+        let screen = libc::dup(1);
+        // Not inherited by the commands, whose output is discarded with cronthat's own.
+        for fd in [saved[0], saved[1], screen] {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+        libc::dup2(null.as_raw_fd(), 1);
+        libc::dup2(null.as_raw_fd(), 2);
+        (saved, screen)
+    };
+    // Restores stdout and stderr if the terminal cannot be set up.
+    let mut tui = Tui {
+        dashboard: Dashboard::new(time_format),
+        saved,
+        stop: Shutdown::default(),
+        drawer: None,
+    };
+    // SAFETY: `screen` was just created and is owned by the file from now on.
+    let screen = unsafe { File::from_raw_fd(screen) };
+    let mut terminal = Terminal::new(CrosstermBackend::new(screen))?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen, Hide)?;
+
+    let dashboard = tui.dashboard.clone();
+    let stop = tui.stop.clone();
+    tui.drawer = Some(std::thread::spawn(move || {
+        loop {
+            let _ = terminal.draw(|frame| dashboard.draw(frame, Local::now()));
+            if stop.sleep(REFRESH_INTERVAL) {
+                break;
+            }
+        }
+        let _ = execute!(terminal.backend_mut(), LeaveAlternateScreen, Show);
+    }));
+    Ok(Some(tui))
+}
+
+#[cfg(not(unix))]
+pub fn start(_time_format: Option<TimestampFormat>) -> Result<Option<Tui>> {
+    warning!("warning: --tui is only supported on Unix, it is ignored");
+    Ok(None)
+}
+
+#[cfg(unix)]
+impl Drop for Tui {
+    fn drop(&mut self) {
+        self.stop.trigger();
+        if let Some(drawer) = self.drawer.take() {
+            let _ = drawer.join();
+        }
+        // SAFETY: the saved file descriptors are valid and owned by the dashboard.
+        unsafe {
+            libc::dup2(self.saved[0], 1);
+            libc::dup2(self.saved[1], 2);
+            libc::close(self.saved[0]);
+            libc::close(self.saved[1]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::timestamp::TimestampFormat;
+    use crate::tui::Dashboard;
+    use chrono::{Duration, Local};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn render(dashboard: &Dashboard) -> String {
+        let mut terminal = Terminal::new(TestBackend::new(60, 12)).unwrap();
+        let now = Local::now();
+        terminal.draw(|frame| dashboard.draw(frame, now)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer
+            .content()
+            .chunks(buffer.area.width as usize)
+            .map(|line| line.iter().map(|cell| cell.symbol()).collect::<String>())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn dashboard_counts_executions() {
+        let dashboard = Dashboard::default();
+        assert!(render(&dashboard).contains("Status:     starting"));
+
+        dashboard.started("./backup.sh");
+        assert!(render(&dashboard).contains("Status:     running for"));
+        dashboard.finished(0, true);
+        dashboard.started("./backup.sh");
+        dashboard.finished(2, false);
+        dashboard.scheduled(Local::now() + Duration::seconds(30));
+
+        let screen = render(&dashboard);
+        assert!(screen.contains("Command:    ./backup.sh"), "{}", screen);
+        assert!(screen.contains("(in 30s)"), "{}", screen);
+        assert!(screen.contains("1 succeeded, 1 failed"), "{}", screen);
+        let history: Vec<&str> = screen
+            .lines()
+            .skip_while(|line| !line.contains("Exit code"))
+            .skip(1)
+            .take(2)
+            .collect();
+        assert!(history[0].contains(" 2 "), "{}", screen);
+        assert!(history[1].contains(" 0 "), "{}", screen);
+    }
+
+    #[test]
+    fn dashboard_time_format() {
+        let format = TimestampFormat::Custom("year %Y".to_string());
+        let dashboard = Dashboard::new(Some(format));
+        dashboard.started("./backup.sh");
+        dashboard.finished(0, true);
+        dashboard.scheduled(Local::now() + Duration::seconds(30));
+
+        let screen = render(&dashboard);
+        let year = Local::now().format("year %Y").to_string();
+        assert!(
+            screen.contains(&format!("next execution at {} (in", year)),
+            "{}",
+            screen
+        );
+        assert_eq!(screen.matches(&year).count(), 2, "{}", screen);
+    }
+}
@@ -0,0 +1,188 @@
+use crate::log::error;
+use crate::shutdown::Shutdown;
+use chrono::Duration;
+use std::io;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+
+/// How often `MemoryLimit` checks the memory used by cronthat.
+static MEMORY_CHECK_INTERVAL: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// Resource usage of a command, as reported by `wait4` when it is reaped. It covers the command
+/// and the processes it waited for.
+#[derive(Clone, Copy, Default)]
+pub struct Usage {
+    /// User and system CPU time.
+    pub cpu: Duration,
+    /// Largest resident set size of its processes, in KiB.
+    pub max_rss_kib: i64,
+}
+
+impl Usage {
+    /// Usage of the stages of a pipeline, which run side by side.
+    pub fn add(self, other: Usage) -> Usage {
+        Usage {
+            cpu: self.cpu + other.cpu,
+            max_rss_kib: self.max_rss_kib.max(other.max_rss_kib),
+        }
+    }
+}
+
+/// Wait for `child` to exit, return its exit status and its own resource usage.
+#[cfg(unix)]
+pub fn wait(child: &mut Child) -> io::Result<(ExitStatus, Usage)> {
+    use std::os::unix::process::ExitStatusExt;
+
+    let pid = child.id() as libc::pid_t;
+    let mut status = 0;
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: wait4 only writes into the provided status and struct.
+    let usage = loop {
+        if unsafe { libc::wait4(pid, &mut status, 0, usage.as_mut_ptr()) } != -1 {
+            break unsafe { usage.assume_init() };
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != io::ErrorKind::Interrupted {
+            return Err(err);
+        }
+    };
+    // The width of the fields depends on the platform.
+    #[allow(clippy::unnecessary_cast)]
+    let time = |time: libc::timeval| {
+        Duration::seconds(time.tv_sec as i64) + Duration::microseconds(time.tv_usec as i64)
+    };
+    #[allow(clippy::unnecessary_cast)]
+    let max_rss = usage.ru_maxrss as i64;
+    let usage = Usage {
+        cpu: time(usage.ru_utime) + time(usage.ru_stime),
+        // macOS reports bytes where other Unix systems report KiB.
+        max_rss_kib: if cfg!(target_os = "macos") {
+            max_rss / 1024
+        } else {
+            max_rss
+        },
+    };
+    Ok((ExitStatus::from_raw(status), usage))
+}
+
+#[cfg(not(unix))]
+pub fn wait(child: &mut Child) -> io::Result<(ExitStatus, Usage)> {
+    Ok((child.wait()?, Usage::default()))
+}
+
+/// Current resident set size of cronthat itself in bytes, the peak one on Unix systems other than
+/// Linux.
+#[cfg(target_os = "linux")]
+pub fn resident_memory() -> Option<u64> {
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    // SAFETY: sysconf has no memory safety requirements.
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+    Some(pages * u64::try_from(page_size).ok()?)
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+pub fn resident_memory() -> Option<u64> {
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::zeroed();
+    // SAFETY: getrusage only writes into the provided struct.
+    let usage = unsafe {
+        libc::getrusage(libc::RUSAGE_SELF, usage.as_mut_ptr());
+        usage.assume_init()
+    };
+    #[allow(clippy::unnecessary_cast)]
+    let max_rss = usage.ru_maxrss as u64;
+    // macOS reports bytes where other Unix systems report KiB.
+    Some(if cfg!(target_os = "macos") {
+        max_rss
+    } else {
+        max_rss * 1024
+    })
+}
+
+#[cfg(not(unix))]
+pub fn resident_memory() -> Option<u64> {
+    None
+}
+
+/// Background check of the memory used by cronthat, triggering `shutdown` once it goes over the
+/// limit. The check stops when it is dropped.
+pub struct MemoryLimit {
+    exceeded: Arc<AtomicBool>,
+    stop: Shutdown,
+}
+
+impl MemoryLimit {
+    pub fn watch(max_bytes: u64, shutdown: &Shutdown) -> MemoryLimit {
+        let limit = MemoryLimit {
+            exceeded: Arc::new(AtomicBool::new(false)),
+            stop: Shutdown::default(),
+        };
+        let (exceeded, stop, shutdown) =
+            (limit.exceeded.clone(), limit.stop.clone(), shutdown.clone());
+        thread::spawn(move || loop {
+            if let Some(used) = resident_memory().filter(|used| *used > max_bytes) {
+                error!(
+                    "error: cronthat uses {} bytes of memory, more than --max-memory {}, stopping",
+                    used, max_bytes
+                );
+                exceeded.store(true, Ordering::SeqCst);
+                shutdown.trigger();
+                return;
+            }
+            if stop.sleep(MEMORY_CHECK_INTERVAL) {
+                return;
+            }
+        });
+        limit
+    }
+
+    pub fn exceeded(&self) -> bool {
+        self.exceeded.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MemoryLimit {
+    fn drop(&mut self) {
+        self.stop.trigger();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::usage::{resident_memory, wait};
+    use std::process::Command;
+
+    #[cfg(unix)]
+    #[test]
+    fn resident_memory_is_reported() {
+        assert!(resident_memory().is_some_and(|used| used > 0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn wait_reports_the_child_usage() {
+        let mut big = Command::new("sh")
+            .args([
+                "-c",
+                "x=$(head -c 50000000 /dev/zero | tr '\\0' x); echo ${#x}",
+            ])
+            .stdout(std::process::Stdio::null())
+            .spawn()
+            .unwrap();
+        let (status, big) = wait(&mut big).unwrap();
+        assert!(status.success());
+
+        // Not the peak of the previous child, unlike getrusage(RUSAGE_CHILDREN).
+        let mut small = Command::new("true").spawn().unwrap();
+        let (status, small) = wait(&mut small).unwrap();
+        assert!(status.success());
+        assert!(
+            small.max_rss_kib < big.max_rss_kib / 2,
+            "{} {}",
+            small.max_rss_kib,
+            big.max_rss_kib
+        );
+    }
+}
@@ -0,0 +1,27 @@
+#![allow(dead_code)]
+
+use std::process::Command;
+
+/// The cronthat binary, without the environment variables it reads its options from so that
+/// the caller's environment doesn't change the output.
+pub fn cronthat() -> Command {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_cronthat"));
+    command.env_remove("CRONTHAT_LOG");
+    command
+}
+
+/// cronthat running its command once, on the next second, `args` ending with the command.
+pub fn cronthat_once(args: &[&str]) -> Command {
+    let mut command = cronthat();
+    command
+        .args(["* * * * * *", "--repetitions", "1"])
+        .args(args);
+    command
+}
+
+/// Stdout of `cronthat_once`, which must exit successfully.
+pub fn run_once(args: &[&str]) -> String {
+    let output = cronthat_once(args).output().unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
@@ -0,0 +1,45 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn config_jobs_take_command_line_options() {
+    let dir = tempfile::tempdir().unwrap();
+    let config_path = dir.path().join("jobs.toml");
+    fs::write(
+        &config_path,
+        r#"
+        [[jobs]]
+        cron_expression = "* * * * * *"
+        command = ["true"]
+        "#,
+    )
+    .unwrap();
+
+    let mut child = common::cronthat()
+        .args([
+            "--config",
+            config_path.to_str().unwrap(),
+            "--repetitions",
+            "1",
+            "--stop-on-error",
+        ])
+        .spawn()
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(5);
+    let status = loop {
+        if let Some(status) = child.try_wait().unwrap() {
+            break status;
+        }
+        if Instant::now() > deadline {
+            child.kill().unwrap();
+            panic!("--repetitions was not applied to the job");
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    assert!(status.success());
+}
@@ -0,0 +1,52 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn daemon_detaches() {
+    let dir = tempfile::tempdir().unwrap();
+    let output_path = dir.path().join("output");
+    let log_path = dir.path().join("cronthat.log");
+    let pid_path = dir.path().join("cronthat.pid");
+
+    let start = Instant::now();
+    let status = common::cronthat()
+        .args([
+            "* * * * * *",
+            "--repetitions",
+            "2",
+            "--daemon",
+            "--log-file",
+            log_path.to_str().unwrap(),
+            "--pid-file",
+            pid_path.to_str().unwrap(),
+            "--",
+            &format!("echo helloworld >> {:?}", output_path),
+        ])
+        .status()
+        .unwrap();
+    assert!(status.success());
+    assert!(
+        start.elapsed() < Duration::from_secs(1),
+        "parent must not wait"
+    );
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while fs::read_to_string(&output_path).unwrap_or_default() != "helloworld\nhelloworld\n" {
+        assert!(Instant::now() < deadline, "daemon did not run the command");
+        thread::sleep(Duration::from_millis(100));
+    }
+    let pid: u32 = fs::read_to_string(&pid_path)
+        .unwrap()
+        .trim()
+        .parse()
+        .unwrap();
+    assert_ne!(pid, std::process::id());
+    assert!(fs::read_to_string(&log_path)
+        .unwrap()
+        .contains("Spawning command"));
+}
@@ -0,0 +1,35 @@
+#![cfg(unix)]
+
+mod common;
+
+use serde_json::Value;
+
+fn run(args: &[&str]) -> String {
+    let args = [
+        &["--count-from", "5"],
+        args,
+        &["--", "echo run-{iteration}"],
+    ]
+    .concat();
+    common::run_once(&args)
+}
+
+#[test]
+fn echo_command_prints_substituted_command() {
+    let stdout = run(&["--echo-command"]);
+    assert!(
+        stdout.contains(" -- Running: echo run-5\nrun-5\n"),
+        "{}",
+        stdout
+    );
+
+    let stdout = run(&[]);
+    assert!(!stdout.contains("Running:"), "{}", stdout);
+
+    let stdout = run(&["--echo-command", "--quiet"]);
+    assert_eq!(stdout, "run-5\n");
+
+    let stdout = run(&["--echo-command", "--quiet", "--json-runs"]);
+    let record: Value = serde_json::from_str(stdout.trim()).unwrap();
+    assert_eq!(record["command"], "echo run-5");
+}
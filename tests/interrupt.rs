@@ -0,0 +1,39 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::process::Stdio;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[test]
+fn interrupt_reaches_command_with_timeout() {
+    let dir = tempfile::tempdir().unwrap();
+    let started_path = dir.path().join("started");
+
+    let mut child = common::cronthat_once(&[
+        "--timeout-per-run",
+        "20s",
+        "--",
+        &format!("touch {:?}; sleep 15", started_path),
+    ])
+    .stdout(Stdio::null())
+    .spawn()
+    .unwrap();
+
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while !started_path.exists() {
+        assert!(Instant::now() < deadline, "command did not start");
+        thread::sleep(Duration::from_millis(50));
+    }
+    // The command runs in its own process group, so only cronthat gets the signal.
+    let interrupted = Instant::now();
+    // SAFETY: kill has no memory safety requirements.
+    unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+    child.wait().unwrap();
+    assert!(
+        interrupted.elapsed() < Duration::from_secs(5),
+        "cronthat waited {:?} for the command",
+        interrupted.elapsed()
+    );
+}
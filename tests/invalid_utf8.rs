@@ -0,0 +1,57 @@
+#![cfg(unix)]
+
+mod common;
+
+#[test]
+fn invalid_utf8_warned_once() {
+    let output = common::cronthat()
+        .args([
+            "* * * * * *",
+            "--now",
+            "--repetitions",
+            "3",
+            "--max-output-lines",
+            "10",
+            "--",
+            r"printf 'caf\351\n'",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("caf\u{fffd}\n").count(), 3, "{}", stdout);
+    assert_eq!(
+        stdout.matches("is not valid UTF-8").count(),
+        1,
+        "{}",
+        stdout
+    );
+}
+
+#[test]
+fn invalid_encoding_warned_once() {
+    let output = common::cronthat()
+        .args([
+            "* * * * * *",
+            "--now",
+            "--repetitions",
+            "3",
+            "--output-encoding",
+            "shift_jis",
+            "--",
+            r"printf 'ok \202\n'",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("ok \u{fffd}").count(), 3, "{}", stdout);
+    assert_eq!(
+        stdout.matches("is not valid Shift_JIS").count(),
+        1,
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,34 @@
+#![cfg(unix)]
+
+mod common;
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::Value;
+
+#[test]
+fn json_runs_round_trip_binary_output() {
+    let output = common::cronthat_once(&[
+        "--json-runs",
+        "--quiet",
+        "--",
+        r"printf '\377\000caf\351\n'; printf 'oops' >&2; exit 3",
+    ])
+    .output()
+    .unwrap();
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines.len(), 1, "{}", stdout);
+    let record: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(record["exit_code"], 3);
+    assert!(record["duration_ms"].is_i64());
+    let decode = |field: &str| {
+        BASE64_STANDARD
+            .decode(record[field].as_str().unwrap())
+            .unwrap()
+    };
+    assert_eq!(decode("stdout"), b"\xff\x00caf\xe9\n");
+    assert_eq!(decode("stderr"), b"oops");
+}
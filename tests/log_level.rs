@@ -0,0 +1,33 @@
+#![cfg(unix)]
+
+mod common;
+
+fn run(args: &[&str], env: Option<&str>) -> String {
+    let mut command = common::cronthat_once(args);
+    if let Some(level) = env {
+        command.env("CRONTHAT_LOG", level);
+    }
+    let output = command.output().unwrap();
+    assert!(output.status.success());
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn log_level_filters_own_messages() {
+    let stdout = run(&["--", "echo out; exit 2"], None);
+    assert!(stdout.contains("-- Spawning command\n"));
+    assert!(stdout.contains("warning: command exited with non-zero status code\n"));
+    assert!(!stdout.contains("-- Next execution at"));
+
+    let stdout = run(&["--log-level", "warn", "--", "echo out; exit 2"], None);
+    assert_eq!(
+        stdout,
+        "out\nwarning: command exited with non-zero status code\n\n"
+    );
+
+    let stdout = run(&["--", "echo out"], Some("debug"));
+    assert!(stdout.contains("-- Next execution at"));
+
+    let stdout = run(&["--quiet", "--", "echo out; exit 2"], Some("trace"));
+    assert_eq!(stdout, "out\n");
+}
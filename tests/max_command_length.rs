@@ -0,0 +1,24 @@
+#![cfg(unix)]
+
+mod common;
+
+#[test]
+fn max_command_length_checked_after_expand_env() {
+    let output = common::cronthat_once(&[
+        "--max-command-length",
+        "16",
+        "--expand-env",
+        "--",
+        "echo $LONG_ARG",
+    ])
+    .env("LONG_ARG", "hello-world-and-beyond")
+    .output()
+    .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(
+        stdout.contains("more than --max-command-length 16"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,46 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::process::Command;
+
+fn hostname() -> String {
+    let output = Command::new("uname").arg("-n").output().unwrap();
+    String::from_utf8(output.stdout).unwrap().trim().to_string()
+}
+
+fn run(args: &[&str]) -> String {
+    let messages = [
+        "--success-message",
+        "OK: done on {hostname}",
+        "--failure-message",
+        "ALERT: failed on {hostname}",
+    ];
+    let output = common::cronthat_once(&[&messages, args].concat())
+        // The host name comes from the system rather than the environment.
+        .env("HOSTNAME", "web1")
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn success_and_failure_messages() {
+    let hostname = hostname();
+    let stdout = run(&["--", "echo out"]);
+    assert!(stdout.ends_with(&format!("s\nOK: done on {}\n", hostname)));
+
+    let stdout = run(&["--", "echo out; exit 2"]);
+    assert!(stdout.contains(&format!("s\nALERT: failed on {}\n", hostname)));
+    assert!(!stdout.contains("OK: done"));
+    assert!(!stdout.contains("non-zero status code"));
+
+    let stdout = run(&["--stop-on-error", "--", "exit 2"]);
+    assert!(stdout.contains(&format!("Caused by:\n    ALERT: failed on {}\n", hostname)));
+
+    assert_eq!(run(&["--quiet", "--", "echo out"]), "out\n");
+    assert_eq!(
+        run(&["--log-level", "warn", "--", "echo out; exit 2"]),
+        format!("out\nALERT: failed on {}\n\n", hostname)
+    );
+}
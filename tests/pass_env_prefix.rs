@@ -0,0 +1,23 @@
+#![cfg(unix)]
+
+mod common;
+
+#[test]
+fn pass_env_prefix_forwards_matching_variables() {
+    let output = common::cronthat_once(&[
+        "--quiet",
+        "--pass-env-prefix",
+        "CRONTHAT_TEST_",
+        "--",
+        "echo $CRONTHAT_TEST_FORWARDED-${CRONTHAT_OTHER_DROPPED:-unset}-${HOME:-unset}",
+    ])
+    .env("CRONTHAT_TEST_FORWARDED", "forwarded")
+    .env("CRONTHAT_OTHER_DROPPED", "dropped")
+    .output()
+    .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "forwarded-unset-unset\n"
+    );
+}
@@ -0,0 +1,18 @@
+#![cfg(unix)]
+
+mod common;
+
+#[test]
+fn tail_on_failure_prints_last_lines() {
+    let run = |command: &str| common::run_once(&["--tail-on-failure", "2", "--", command]);
+
+    let stdout = run("seq 1 5; exit 3");
+    assert!(stdout.contains("Last 2 lines of the output of the failed execution:\n4\n5\n"));
+    for line in ["1", "2", "3"] {
+        assert!(!stdout.lines().any(|output_line| output_line == line));
+    }
+
+    let stdout = run("seq 1 5");
+    assert!(!stdout.contains("Last 2 lines"));
+    assert!(!stdout.lines().any(|line| line == "5"));
+}
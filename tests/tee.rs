@@ -0,0 +1,53 @@
+#![cfg(unix)]
+
+mod common;
+
+use std::fs;
+
+#[test]
+fn tee_duplicates_output() {
+    let dir = tempfile::tempdir().unwrap();
+    let first_path = dir.path().join("first.log");
+    let second_path = dir.path().join("second.log");
+
+    let stdout = common::run_once(&[
+        "--tee",
+        first_path.to_str().unwrap(),
+        "--tee",
+        second_path.to_str().unwrap(),
+        "--",
+        "echo",
+        "helloworld",
+    ]);
+    assert!(stdout.contains("Spawning command"));
+    assert!(stdout.contains("helloworld\n"));
+    for path in [first_path, second_path] {
+        assert_eq!(fs::read_to_string(path).unwrap(), stdout);
+    }
+}
+
+#[test]
+fn tee_duplicates_errors() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("cronthat.log");
+
+    let output = common::cronthat_once(&[
+        "--tee",
+        path.to_str().unwrap(),
+        "--stop-on-error",
+        "--",
+        "echo out; echo err >&2; exit 2",
+    ])
+    .output()
+    .unwrap();
+    assert!(!output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert_eq!(stderr, "err\n");
+    assert!(stdout.contains("Something went wrong"), "{}", stdout);
+
+    let content = fs::read_to_string(path).unwrap();
+    assert!(content.contains("out\n"), "{}", content);
+    assert!(content.contains("err\n"), "{}", content);
+    assert!(content.contains("Something went wrong"), "{}", content);
+}
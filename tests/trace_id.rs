@@ -0,0 +1,19 @@
+#![cfg(unix)]
+
+mod common;
+
+#[test]
+fn trace_id_from_environment() {
+    let output = common::cronthat_once(&[
+        "--quiet",
+        "--trace-id-from",
+        "CRONTHAT_TEST_TRACE_ID",
+        "--",
+        "echo $CRONTHAT_TRACE_ID",
+    ])
+    .env("CRONTHAT_TEST_TRACE_ID", "external-id")
+    .output()
+    .unwrap();
+    assert!(output.status.success(), "{:?}", output);
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "external-id\n");
+}